@@ -542,7 +542,7 @@ impl Allocator for SimpleAllocator {
             self.end,
             self.min_align.max(mmu::UAT_PGSZ) as u64,
             self.prot,
-            true,
+            1,
         )?;
 
         let ptr = unsafe { p.add(offset) } as *mut u8;
@@ -777,7 +777,8 @@ impl HeapAllocator {
         }
 
         let gpu_ptr = self.top;
-        if let Err(e) = obj.map_at(&self.vm, gpu_ptr, self.prot, self.cpu_maps) {
+        let guard_pages = if self.cpu_maps { 1 } else { 0 };
+        if let Err(e) = obj.map_at(&self.vm, gpu_ptr, self.prot, guard_pages) {
             dev_err!(
                 &self.dev,
                 "HeapAllocator[{}]::add_block: Failed to map at {:#x} ({:?})\n",
@@ -43,10 +43,45 @@ module_platform_driver! {
             permissions: 0,
             description: "Fault control (0x0: hard faults, 0xb: macOS default)",
         },
+        num_events: u32 {
+            default: 128,
+            permissions: 0,
+            description: "Number of GPU event slots, limiting concurrently active queues (max 128)",
+        },
+        rotate_events: bool {
+            default: false,
+            permissions: 0o644,
+            description: "Spread event slots across queues instead of reusing each queue's last slot",
+        },
         initial_tvb_size: usize {
             default: 0x8,
             permissions: 0o644,
             description: "Initial TVB size in blocks",
         },
+        submit_retries: u32 {
+            default: 0,
+            permissions: 0o644,
+            description: "Number of retries (at least 1ms apart) when a firmware pipe channel is full before failing the submission with EAGAIN (0: wait forever)",
+        },
+        submit_rate_limit: u32 {
+            default: 0,
+            permissions: 0o644,
+            description: "Maximum submissions per second per client, CAP_SYS_NICE exempt (0: unlimited)",
+        },
+        deferred_free_batch: u32 {
+            default: 0,
+            permissions: 0o644,
+            description: "Number of completed work items to accumulate before freeing them on the system work queue (0: free each one)",
+        },
+        recovery_submit_wait_ms: u32 {
+            default: 0,
+            permissions: 0o644,
+            description: "Time submissions wait for GPU recovery to finish before failing with EAGAIN",
+        },
+        inject_error: u32 {
+            default: 0,
+            permissions: 0o644,
+            description: "Error injected by the InjectError debug flag (1: timeout, 2: fault, 3: killed, 4: no device, 5: out of memory)",
+        },
     },
 }
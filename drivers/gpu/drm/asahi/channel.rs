@@ -152,6 +152,24 @@ where
         self.wptr
     }
 
+    /// Try to send a message to the ring, returning a cookie with the ring buffer position.
+    ///
+    /// Unlike `put()`, this does not block if the ring is full, and returns EAGAIN instead.
+    pub(crate) fn try_put(&mut self, msg: &U) -> Result<u32> {
+        self.ring.state.with(|raw, _inner| {
+            let next_wptr = (self.wptr + 1) % self.count;
+            if next_wptr == T::rptr(raw) {
+                return Err(EAGAIN);
+            }
+            self.ring.ring[self.wptr as usize] = *msg;
+            mem::sync();
+            T::set_wptr(raw, next_wptr);
+            self.wptr = next_wptr;
+            Ok(())
+        })?;
+        Ok(self.wptr)
+    }
+
     /// Wait for a previously submitted message to be popped off of the ring by the GPU firmware.
     ///
     /// This busy-loops, and is intended to be used for rare cases when we need to block for
@@ -251,6 +269,14 @@ impl PipeChannel::ver {
         cls_dev_dbg!(PipeCh, self.dev, "Pipe: {:?}\n", msg);
         self.ch.put(msg);
     }
+
+    /// Tries to submit a Pipe kick command to the firmware, returning EAGAIN if the channel is
+    /// full.
+    pub(crate) fn try_send(&mut self, msg: &PipeMsg::ver) -> Result {
+        cls_dev_dbg!(PipeCh, self.dev, "Pipe: {:?}\n", msg);
+        self.ch.try_put(msg)?;
+        Ok(())
+    }
 }
 
 /// Firmware Control channel, used for secure cache flush requests.
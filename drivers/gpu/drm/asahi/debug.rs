@@ -5,9 +5,10 @@
 
 #[allow(unused_imports)]
 pub(crate) use super::{cls_dev_dbg, cls_pr_debug, debug, mod_dev_dbg, mod_pr_debug};
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 static DEBUG_FLAGS: AtomicU64 = AtomicU64::new(0);
+static INJECT_ARMED: AtomicBool = AtomicBool::new(false);
 
 /// Debug flag bit indices
 pub(crate) enum DebugFlags {
@@ -54,6 +55,10 @@ pub(crate) enum DebugFlags {
     WaitForPowerOff = 38,
     NoGpuRecovery = 39,
     DisableClustering = 40,
+    PollCompletion = 41,
+    DumpInitData = 42,
+    ValidatePointers = 43,
+    InjectError = 44,
 
     // 48-: Misc
     Debug0 = 48,
@@ -76,7 +81,48 @@ pub(crate) fn update_debug_flags() {
         *crate::debug_flags.read(&lock)
     };
 
-    DEBUG_FLAGS.store(flags, Ordering::Relaxed);
+    let old = DEBUG_FLAGS.swap(flags, Ordering::Relaxed);
+
+    // Setting the InjectError flag arms a single injection, so clearing and setting it again
+    // injects one more error.
+    let inject = 1 << (DebugFlags::InjectError as usize);
+    if flags & inject != 0 && old & inject == 0 {
+        INJECT_ARMED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Error to inject into the next completed command, as selected by the `inject_error` module
+/// parameter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum InjectedError {
+    Timeout,
+    Fault,
+    Killed,
+    NoDevice,
+    OutOfMemory,
+}
+
+/// Consume the armed error injection, if any.
+///
+/// Returns the error to inject at most once each time the `InjectError` debug flag is set.
+pub(crate) fn take_injected_error() -> Option<InjectedError> {
+    if !debug_enabled(DebugFlags::InjectError) || !INJECT_ARMED.swap(false, Ordering::Relaxed) {
+        return None;
+    }
+
+    let kind = {
+        let lock = crate::THIS_MODULE.kernel_param_lock();
+        *crate::inject_error.read(&lock)
+    };
+
+    match kind {
+        1 => Some(InjectedError::Timeout),
+        2 => Some(InjectedError::Fault),
+        3 => Some(InjectedError::Killed),
+        4 => Some(InjectedError::NoDevice),
+        5 => Some(InjectedError::OutOfMemory),
+        _ => None,
+    }
 }
 
 /// Check whether debug is enabled for a given flag
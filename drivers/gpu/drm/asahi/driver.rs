@@ -9,7 +9,10 @@ use kernel::{
 use crate::{debug, file, gem, gpu, hw, regs};
 
 use kernel::device::RawDevice;
+use kernel::drm::debugfs::ShowFile;
 use kernel::macros::vtable;
+use kernel::seq_file::SeqFile;
+use kernel::sync::ArcBorrow;
 use kernel::types::ARef;
 
 /// Driver metadata
@@ -22,16 +25,27 @@ const INFO: drv::DriverInfo = drv::DriverInfo {
     date: c_str!("20220831"),
 };
 
+/// Time to wait for the GPU to go idle when removing the device.
+const REMOVE_IDLE_TIMEOUT_MS: usize = 1000;
+/// Time to wait for clients to let go of the GPU when removing the device.
+const REMOVE_HOLDERS_TIMEOUT_MS: u32 = 1000;
+
 /// Device data for the driver registration.
 ///
 /// Holds a reference to the top-level `GpuManager` object.
 pub(crate) struct AsahiData {
     pub(crate) dev: device::Device,
     pub(crate) gpu: Arc<dyn gpu::GpuManager>,
+    /// MMIO resources. These live here rather than in the `device::Data` resources, and after
+    /// `gpu`, so that the coprocessor CPU is only stopped (when they are dropped) once the GPU
+    /// manager and its RTKit instance are gone.
+    pub(crate) regs: regs::Resources,
+    /// Version of the GPU firmware in use, from the `apple,firmware-compat` property.
+    pub(crate) fw_version: Vec<u32>,
 }
 
 /// Convenience type alias for the `device::Data` type for this driver.
-type DeviceData = device::Data<drv::Registration<AsahiDriver>, regs::Resources, AsahiData>;
+type DeviceData = device::Data<drv::Registration<AsahiDriver>, (), AsahiData>;
 
 /// Empty struct representing this driver.
 pub(crate) struct AsahiDriver;
@@ -73,6 +87,26 @@ impl drv::Driver for AsahiDriver {
             ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::queue_destroy),
         (ASAHI_SUBMIT,          drm_asahi_submit,
             ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::submit),
+        (ASAHI_GEM_POPULATE,    drm_asahi_gem_populate,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::gem_populate),
+        (ASAHI_PERFMON,         drm_asahi_perfmon,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::perfmon),
+        (ASAHI_GEM_BUSY,        drm_asahi_gem_busy,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::gem_busy),
+        (ASAHI_TRIM,            drm_asahi_trim,
+               ioctl::AUTH | ioctl::ROOT_ONLY, file::File::trim),
+        (ASAHI_GET_NAME,        drm_asahi_get_name,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::get_name),
+        (ASAHI_VM_INFO,         drm_asahi_vm_info,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::vm_info),
+        (ASAHI_GEM_BIND_BATCH,  drm_asahi_gem_bind_batch,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::gem_bind_batch),
+        (ASAHI_GEM_GET_IOVA,    drm_asahi_gem_get_iova,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::gem_get_iova),
+        (ASAHI_GEM_SET_LABEL,   drm_asahi_gem_set_label,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::gem_set_label),
+        (ASAHI_GEM_CREATE_BIND, drm_asahi_gem_create_bind,
+            ioctl::AUTH | ioctl::RENDER_ALLOW, file::File::gem_create_bind),
     }
 }
 
@@ -87,6 +121,31 @@ kernel::define_of_id_table! {ASAHI_ID_TABLE, &'static hw::HwConfig, [
     (of::DeviceId::Compatible(b"apple,agx-t6021"), Some(&hw::t602x::HWCONFIG_T6021)),
 ]}
 
+/// debugfs file listing all in-flight work, for hang debugging.
+struct InFlight;
+
+impl ShowFile<AsahiDriver> for InFlight {
+    const NAME: &'static CStr = c_str!("in_flight");
+
+    fn show(data: ArcBorrow<'_, DeviceData>, m: &SeqFile) -> Result {
+        data.gpu.show_in_flight(m);
+        Ok(())
+    }
+}
+
+/// debugfs file with the pipe channel retry count and the occupancy of every busy queue, for
+/// profiling stalls.
+struct QueueStats;
+
+impl ShowFile<AsahiDriver> for QueueStats {
+    const NAME: &'static CStr = c_str!("queue_stats");
+
+    fn show(data: ArcBorrow<'_, DeviceData>, m: &SeqFile) -> Result {
+        data.gpu.show_stats(m);
+        Ok(())
+    }
+}
+
 /// Platform Driver implementation for `AsahiDriver`.
 impl platform::Driver for AsahiDriver {
     /// Our `DeviceData` type, reference-counted
@@ -124,6 +183,8 @@ impl platform::Driver for AsahiDriver {
         let compat: Vec<u32> = node.get_property(c_str!("apple,firmware-compat"))?;
 
         let reg = drm::drv::Registration::<AsahiDriver>::new(&dev)?;
+        reg.device().debugfs_add_file::<InFlight>();
+        reg.device().debugfs_add_file::<QueueStats>();
         let gpu = match (cfg.gpu_gen, cfg.gpu_variant, compat.as_slice()) {
             (hw::GpuGen::G13, _, &[12, 3, 0]) => {
                 gpu::GpuManagerG13V12_3::new(reg.device(), &res, cfg)? as Arc<dyn gpu::GpuManager>
@@ -151,8 +212,19 @@ impl platform::Driver for AsahiDriver {
             }
         };
 
-        let data =
-            kernel::new_device_data!(reg, res, AsahiData { dev, gpu }, "Asahi::Registrations")?;
+        dev_info!(dev, "Firmware version: {:?}\n", compat);
+
+        let data = kernel::new_device_data!(
+            reg,
+            (),
+            AsahiData {
+                dev,
+                gpu,
+                regs: res,
+                fw_version: compat,
+            },
+            "Asahi::Registrations"
+        )?;
 
         let data: Arc<DeviceData> = data.into();
 
@@ -168,6 +240,61 @@ impl platform::Driver for AsahiDriver {
         dev_info!(data.dev, "Probed!\n");
         Ok(data)
     }
+
+    /// Device remove function.
+    ///
+    /// Quiesces the GPU before the driver data is torn down. This first waits a bounded time for
+    /// clients to close their files and queues and for submissions to complete, logging what is
+    /// still held if they do not, and then waits for the firmware to go idle. The coprocessor CPU keeps running, since the GPU manager and RTKit may still use it
+    /// until the driver data is dropped, which stops it (see `AsahiData::regs`).
+    fn remove(data: &Self::Data) -> Result {
+        dev_info!(data.dev, "Removing...\n");
+
+        let gpu = &data.gpu;
+
+        // This logs whatever is still held on timeout. Teardown goes ahead regardless, since
+        // removal cannot fail.
+        let _ = gpu.wait_for_holders(REMOVE_HOLDERS_TIMEOUT_MS);
+
+        if gpu.wait_for_poweroff(REMOVE_IDLE_TIMEOUT_MS).is_err() {
+            dev_warn!(
+                data.dev,
+                "GPU did not go idle, work may still be outstanding\n"
+            );
+        }
+
+        // Nothing deferred may run once the driver is gone.
+        gpu.free_garbage();
+
+        dev_info!(data.dev, "Removed\n");
+        Ok(())
+    }
+
+    /// Device suspend function.
+    ///
+    /// Holds off new submissions and waits for all queues to drain, failing the suspend if they
+    /// do not. RTKit is not shut down and the coprocessor CPU is left running, since the firmware
+    /// cannot be restarted without a full reprobe. Once idle, the firmware powers the GPU off on
+    /// its own, as it does at runtime.
+    fn suspend(data: ArcBorrow<'_, DeviceData>) -> Result {
+        dev_info!(data.dev, "Suspending...\n");
+        data.gpu.suspend()?;
+        dev_info!(data.dev, "Suspended\n");
+        Ok(())
+    }
+
+    /// Device resume function.
+    ///
+    /// Nothing needs to be reinitialized, since the firmware kept running through the suspend.
+    /// This checks that it did: if the coprocessor CPU was reset or the firmware crashed, its
+    /// state is gone and the device is marked as lost.
+    fn resume(data: ArcBorrow<'_, DeviceData>) -> Result {
+        dev_info!(data.dev, "Resuming...\n");
+
+        data.gpu.resume(data.regs.cpu_running())?;
+        dev_info!(data.dev, "Resumed\n");
+        Ok(())
+    }
 }
 
 // Export the OF ID table as a module ID table, to make modpost/autoloading work.
@@ -14,14 +14,19 @@ use crate::fw::types::*;
 use crate::{gpu, slotalloc, workqueue};
 use core::cmp;
 use core::sync::atomic::Ordering;
+use core::time::Duration;
 use kernel::prelude::*;
+use kernel::seq_file::SeqFile;
+use kernel::seq_print;
 use kernel::sync::Arc;
-use kernel::{c_str, static_lock_class};
+use kernel::{c_str, static_lock_class, time};
 
 const DEBUG_CLASS: DebugFlags = DebugFlags::Event;
 
-/// Number of events managed by the firmware.
-const NUM_EVENTS: u32 = 128;
+/// Maximum number of events that can be configured.
+///
+/// This is the number of events known to work with the firmware; it may only be lowered.
+const MAX_EVENTS: u32 = 128;
 
 /// Inner data associated with a given event slot.
 pub(crate) struct EventInner {
@@ -47,6 +52,11 @@ pub(crate) type Event = slotalloc::Guard<EventInner>;
 pub(crate) struct EventValue(u32);
 
 impl EventValue {
+    /// Returns the raw stamp value.
+    pub(crate) fn raw(&self) -> u32 {
+        self.0
+    }
+
     /// Returns the `EventValue` that succeeds this one.
     pub(crate) fn next(&self) -> EventValue {
         EventValue(self.0.wrapping_add(0x100))
@@ -140,19 +150,35 @@ impl EventManager {
     /// Create a new EventManager.
     #[inline(never)]
     pub(crate) fn new(alloc: &mut gpu::KernelAllocators) -> Result<EventManager> {
+        let num_events = {
+            let lock = crate::THIS_MODULE.kernel_param_lock();
+            *crate::num_events.read(&lock)
+        };
+
+        if num_events == 0 || num_events > MAX_EVENTS {
+            pr_err!(
+                "EventManager: num_events must be between 1 and {} (got {})\n",
+                MAX_EVENTS,
+                num_events
+            );
+            return Err(EINVAL);
+        }
+
+        mod_pr_debug!("EventManager: Using {} event slots\n", num_events);
+
         let mut owners = Vec::new();
-        for _i in 0..(NUM_EVENTS as usize) {
+        for _i in 0..(num_events as usize) {
             owners.try_push(None)?;
         }
         let inner = EventManagerInner {
-            stamps: alloc.shared.array_empty(NUM_EVENTS as usize)?,
-            fw_stamps: alloc.private.array_empty(NUM_EVENTS as usize)?,
+            stamps: alloc.shared.array_empty(num_events as usize)?,
+            fw_stamps: alloc.private.array_empty(num_events as usize)?,
             owners,
         };
 
         Ok(EventManager {
             alloc: slotalloc::SlotAllocator::new(
-                NUM_EVENTS,
+                num_events,
                 inner,
                 |inner: &mut EventManagerInner, slot| EventInner {
                     stamp: &inner.stamps[slot as usize].0,
@@ -167,11 +193,26 @@ impl EventManager {
     }
 
     /// Gets a free `Event`, optionally trying to reuse the last one allocated by this caller.
+    ///
+    /// If the `rotate_events` module parameter is set and any other owner currently holds an
+    /// event, the reuse hint is ignored and the least recently used free slot is handed out
+    /// instead. This spreads slots across queues under contention, while a lone active queue
+    /// still keeps reusing its previous slot.
     pub(crate) fn get(
         &self,
         token: Option<Token>,
         owner: Arc<dyn workqueue::WorkQueue + Send + Sync>,
     ) -> Result<Event> {
+        let rotate = {
+            let lock = crate::THIS_MODULE.kernel_param_lock();
+            *crate::rotate_events.read(&lock)
+        };
+        let token = if rotate && token.is_some() && self.has_owners() {
+            None
+        } else {
+            token
+        };
+
         let ev = self.alloc.get_inner(token, |inner, ev| {
             mod_pr_debug!(
                 "EventManager: Registered owner {:p} on slot {}\n",
@@ -184,6 +225,12 @@ impl EventManager {
         Ok(ev)
     }
 
+    /// Returns whether any event slot currently has an owner.
+    fn has_owners(&self) -> bool {
+        self.alloc
+            .with_inner(|inner| inner.owners.iter().any(|o| o.is_some()))
+    }
+
     /// Signals an event by slot, indicating completion (of one or more commands).
     pub(crate) fn signal(&self, slot: u32) {
         match self
@@ -191,7 +238,13 @@ impl EventManager {
             .with_inner(|inner| inner.owners[slot as usize].as_ref().cloned())
         {
             Some(owner) => {
-                owner.signal();
+                let result = owner.signal();
+                if result.completed_commands == 0 {
+                    mod_pr_debug!(
+                        "EventManager: Event slot {} signaled with no completed commands\n",
+                        slot
+                    );
+                }
             }
             None => {
                 mod_pr_debug!("EventManager: Received event for empty slot {}\n", slot);
@@ -199,6 +252,121 @@ impl EventManager {
         }
     }
 
+    /// Polls all events by checking their stamps directly, completing any finished work.
+    ///
+    /// This bypasses the firmware event channel, and is only meant as a debugging aid when the
+    /// normal signaling path is broken. Returns true if no owner has any pending work left.
+    pub(crate) fn poll_all(&self) -> bool {
+        let mut owners: Vec<Arc<dyn workqueue::WorkQueue + Send + Sync>> = Vec::new();
+
+        self.alloc.with_inner(|inner| {
+            for wq in inner.owners.iter().filter_map(|o| o.as_ref()).cloned() {
+                if owners.try_push(wq).is_err() {
+                    pr_err!("Failed to poll WorkQueue\n");
+                }
+            }
+        });
+
+        let mut idle = true;
+        for wq in owners {
+            idle &= wq.signal().now_empty;
+        }
+
+        idle
+    }
+
+    /// Logs a summary of the in-flight work of every event owner, returning how many had any.
+    ///
+    /// Owners are collected under the allocator lock and dumped after it is released, since each
+    /// owner takes its own lock, in the same order as the normal signaling path.
+    pub(crate) fn dump_all(&self) -> usize {
+        let mut owners: Vec<Arc<dyn workqueue::WorkQueue + Send + Sync>> = Vec::new();
+
+        self.alloc.with_inner(|inner| {
+            for wq in inner.owners.iter().filter_map(|o| o.as_ref()).cloned() {
+                if owners.try_push(wq).is_err() {
+                    pr_err!("Failed to dump WorkQueue\n");
+                }
+            }
+        });
+
+        let now = time::ktime_get();
+        owners.iter().filter(|wq| wq.dump_pending(now)).count()
+    }
+
+    /// Writes the in-flight work of every event owner to `m`, one line per pending command.
+    ///
+    /// Takes locks in the same order as `dump_all()`.
+    pub(crate) fn show_all(&self, m: &SeqFile) {
+        let mut owners: Vec<Arc<dyn workqueue::WorkQueue + Send + Sync>> = Vec::new();
+
+        self.alloc.with_inner(|inner| {
+            for wq in inner.owners.iter().filter_map(|o| o.as_ref()).cloned() {
+                if owners.try_push(wq).is_err() {
+                    pr_err!("Failed to show WorkQueue\n");
+                }
+            }
+        });
+
+        let now = time::ktime_get();
+        for wq in owners {
+            wq.show_pending(m, now);
+        }
+    }
+
+    /// Writes the occupancy of every event owner to `m`, one line per queue.
+    pub(crate) fn show_stats(&self, m: &SeqFile) {
+        let mut owners: Vec<Arc<dyn workqueue::WorkQueue + Send + Sync>> = Vec::new();
+
+        self.alloc.with_inner(|inner| {
+            for wq in inner.owners.iter().filter_map(|o| o.as_ref()).cloned() {
+                if owners.try_push(wq).is_err() {
+                    pr_err!("Failed to show WorkQueue stats\n");
+                }
+            }
+        });
+
+        for wq in owners {
+            let stats = wq.stats();
+            seq_print!(
+                m,
+                "queue={:?} ring={}/{} cmds={} jobs={} stalls={} stall_ms={}\n",
+                wq.pipe_type(),
+                stats.ring_used,
+                stats.ring_size,
+                stats.pending_commands,
+                stats.jobs_in_flight,
+                stats.submit_stalls,
+                stats.submit_stall_time.as_millis()
+            );
+        }
+    }
+
+    /// Waits for every event owner to go idle, with an overall timeout.
+    ///
+    /// Any queue with work in flight holds an event, so this covers all in-flight work on the
+    /// device. Queues that acquire an event after the owners are collected are not waited for,
+    /// so the caller must block new submissions first. Returns `ETIMEDOUT` if some owner did not
+    /// go idle in time.
+    pub(crate) fn wait_idle_all(&self, timeout_ms: u32) -> Result {
+        let mut owners: Vec<Arc<dyn workqueue::WorkQueue + Send + Sync>> = Vec::new();
+
+        self.alloc.with_inner(|inner| -> Result {
+            for wq in inner.owners.iter().filter_map(|o| o.as_ref()).cloned() {
+                owners.try_push(wq)?;
+            }
+            Ok(())
+        })?;
+
+        let deadline = time::ktime_get() + Duration::from_millis(timeout_ms.into());
+        for wq in owners {
+            let remaining = deadline.saturating_sub(time::ktime_get()).as_millis();
+            wq.wait_idle(remaining.try_into().unwrap_or(u32::MAX))?;
+        }
+
+        Ok(())
+    }
+
     /// Marks the owner of an event as having lost its work due to a GPU error.
     pub(crate) fn mark_error(&self, slot: u32, wait_value: u32, error: workqueue::WorkError) {
         match self
@@ -9,20 +9,31 @@
 
 use crate::debug::*;
 use crate::driver::AsahiDevice;
-use crate::{alloc, buffer, driver, gem, mmu, queue};
+use crate::{alloc, buffer, driver, gem, gpu, mem, mmu, queue, workqueue};
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use core::time::Duration;
 use kernel::dma_fence::RawDmaFence;
 use kernel::drm::gem::BaseObject;
 use kernel::io_buffer::{IoBufferReader, IoBufferWriter};
 use kernel::prelude::*;
+use kernel::str::CString;
 use kernel::sync::{Arc, Mutex};
-use kernel::user_ptr::UserSlicePtr;
-use kernel::{dma_fence, drm, uapi, xarray};
+use kernel::user_ptr::{UserSlicePtr, UserSlicePtrReader};
+use kernel::{bindings, dma_fence, drm, time, uapi, xarray};
 
 const DEBUG_CLASS: DebugFlags = DebugFlags::File;
 
 const MAX_SYNCS_PER_SUBMISSION: u32 = 64;
 const MAX_COMMANDS_PER_SUBMISSION: u32 = 64;
+const MAX_SYNC_BOS_PER_SUBMISSION: u32 = 64;
+const MAX_BINDS_PER_BATCH: u32 = 256;
+/// Maximum number of VMs a single client may hold at once.
+const MAX_VMS_PER_FILE: u32 = 64;
+/// Maximum number of queues a single client may hold at once.
+const MAX_QUEUES_PER_FILE: u32 = 256;
+/// Maximum number of guard pages that may follow a user mapping.
+const MAX_GUARD_PAGES: u32 = 1024;
 pub(crate) const MAX_COMMANDS_IN_FLIGHT: u32 = 1024;
 
 /// A client instance of an `mmu::Vm` address space.
@@ -31,12 +42,140 @@ struct Vm {
     ualloc_priv: Arc<Mutex<alloc::DefaultAllocator>>,
     vm: mmu::Vm,
     dummy_obj: gem::ObjectRef,
+    /// Whether this is a compute-only VM, which has no shader region.
+    compute_only: bool,
+    /// General user mapping region for this VM, as an inclusive range.
+    user_range: core::ops::RangeInclusive<u64>,
+    /// Registry of user objects that have been bound into this VM. Bind and unbind operations
+    /// hold its lock while they change mappings, so they are serialized per VM. It is taken
+    /// before any object mapping lock.
+    bound_objs: Arc<Mutex<BoundObjects>>,
+    /// Number of live queues created on this VM, see `VmQueueRef`.
+    queue_count: Arc<AtomicU32>,
+}
+
+/// An object bound into a `Vm`, as tracked by a `BoundObjects` registry.
+struct BoundEntry {
+    bo: gem::ObjectRef,
+    /// Number of mappings of `bo` in the `Vm`. Only modified with the registry lock held.
+    mappings: AtomicU32,
+}
+
+/// Registry of user objects bound into a `Vm`, keyed by GEM object ID.
+///
+/// Keying by object keeps removal cheap, which matters because closing a handle removes the object
+/// from every `Vm` of the file, and DRM closes every handle one by one when the file goes away.
+struct BoundObjects(Pin<Box<xarray::XArray<Box<BoundEntry>>>>);
+
+impl BoundObjects {
+    fn new() -> Result<BoundObjects> {
+        Ok(BoundObjects(Box::into_pin(Box::try_new(
+            xarray::XArray::new(0),
+        )?)))
+    }
+
+    /// Record one more mapping of an object.
+    fn add(&mut self, bo: &gem::ObjectRef) -> Result {
+        let entry = self.0.as_ref().get_or_insert_with(bo.id() as usize, || {
+            Ok(Box::try_new(BoundEntry {
+                bo: bo.clone_ref(),
+                mappings: AtomicU32::new(0),
+            })?)
+        })?;
+        entry.borrow().mappings.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Forget one mapping of an object, removing the object once it has none left.
+    fn remove_one(&mut self, bo_id: u64) {
+        let last = match self.0.as_ref().get(bo_id as usize) {
+            Some(entry) => entry.borrow().mappings.fetch_sub(1, Ordering::Relaxed) == 1,
+            None => false,
+        };
+        if last {
+            self.0.as_ref().remove(bo_id as usize);
+        }
+    }
+
+    /// Remove an object, however many mappings it has.
+    fn remove_all(&mut self, bo_id: u64) {
+        self.0.as_ref().remove(bo_id as usize);
+    }
+
+    /// Remove every object from the registry, handing each one to `f`.
+    fn drain(&mut self, mut f: impl FnMut(gem::ObjectRef)) -> Result {
+        let xa = self.0.as_ref();
+        let mut ids = Vec::new();
+        ids.try_reserve(xa.count())?;
+
+        // Entries cannot be removed under the XArray lock, so collect their IDs first.
+        xa.for_each(|id, _| {
+            if ids.len() < ids.capacity() {
+                let _ = ids.try_push(id);
+            }
+        });
+
+        for id in ids {
+            if let Some(entry) = xa.remove(id) {
+                f(entry.bo);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A counted reference from a queue to the `Vm` it was created on, which keeps `vm_destroy`
+/// from destroying that `Vm` while the queue exists.
+struct VmQueueRef(Arc<AtomicU32>);
+
+impl VmQueueRef {
+    fn new(count: &Arc<AtomicU32>) -> VmQueueRef {
+        count.fetch_add(1, Ordering::Relaxed);
+        VmQueueRef(count.clone())
+    }
+}
+
+impl Drop for VmQueueRef {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A client instance of a submission queue.
+struct Queue {
+    queue: Arc<Mutex<Box<dyn queue::Queue>>>,
+    _vm_ref: VmQueueRef,
+    _gpu_ref: gpu::HolderRef,
 }
 
 impl Drop for Vm {
     fn drop(&mut self) {
+        let vm_id = self.vm.id();
+
+        // Proactively unmap every object still bound into this VM in one pass, so the page
+        // tables are empty before the `mmu::Vm` is torn down, regardless of object drop order.
+        // This is what `vm_destroy` relies on. On file close, DRM has already closed every handle
+        // by now, which dropped the mappings and emptied the registry.
+        let mut count = 0;
+        let ret = self.bound_objs.lock().drain(|mut obj| {
+            obj.drop_vm_mappings(vm_id);
+            count += 1;
+        });
+        mod_pr_debug!(
+            "[VM {}]: Dropped mappings for {} bound objects\n",
+            vm_id,
+            count
+        );
+        if ret.is_err() {
+            pr_err!(
+                "[VM {}]: Out of memory, remaining objects stay mapped until they are freed\n",
+                vm_id
+            );
+        }
+
         // Mappings create a reference loop, make sure to break it.
-        self.dummy_obj.drop_vm_mappings(self.vm.id());
+        self.dummy_obj.drop_vm_mappings(vm_id);
     }
 }
 
@@ -125,11 +264,75 @@ impl SyncItem {
     }
 }
 
+/// A single GEM bind operation, as passed to `gem_bind` or as one entry of `gem_bind_batch`.
+struct BindOp {
+    handle: u32,
+    flags: u32,
+    offset: u64,
+    range: u64,
+    addr: u64,
+    guard_pages: u32,
+}
+
+/// The `Vm` targeted by bind operations, with the state needed to validate them.
+struct BindTarget {
+    vm: mmu::Vm,
+    compute_only: bool,
+    user_range: core::ops::RangeInclusive<u64>,
+    bound_objs: Arc<Mutex<BoundObjects>>,
+}
+
+/// An object mapped by a bind operation that has not been added to the `Vm` registry yet.
+struct BoundObject {
+    bo: gem::ObjectRef,
+    addr: usize,
+    range: usize,
+}
+
+/// Per-client submission rate tracking, used for optional throttling.
+struct SubmitRate {
+    /// Start time of the current one-second accounting window.
+    window_start: Duration,
+    /// Number of submissions accepted within the current window.
+    count: u32,
+}
+
+/// Cumulative per-client usage counters, as returned by `File::stats()`.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct FileStats {
+    /// Total number of commands successfully submitted.
+    pub(crate) commands_submitted: u64,
+    /// Total number of bytes bound into this client's VMs (not reduced by unbinding).
+    pub(crate) bytes_bound: u64,
+}
+
 /// State associated with a client.
+///
+/// # Lock ordering
+///
+/// The `vms` and `queues` XArrays each have their own internal lock. Code paths that need to look
+/// at both must take them in this order: `vms` first, then `queues`, and must never hold an XArray
+/// guard across a call into the GPU manager or a queue `Mutex`. In practice, every path looks up
+/// what it needs, clones out the `Arc`s and drops the XArray guard before touching the other
+/// XArray. Nothing enforces this: all XArrays share a single lockdep class, so lockdep cannot
+/// tell the two locks apart and will not flag an inversion between them.
 pub(crate) struct File {
     id: u64,
+    /// By the time a `File` is dropped, DRM has closed all of its GEM handles, which already
+    /// dropped their mappings and removed them from the VM registries.
     vms: xarray::XArray<Box<Vm>>,
-    queues: xarray::XArray<Arc<Mutex<Box<dyn queue::Queue>>>>,
+    queues: xarray::XArray<Box<Queue>>,
+    /// Performance counter snapshot taken when the active counter session was started, if any.
+    perfmon: Arc<Mutex<Option<Vec<u8>>>>,
+    /// Submission rate tracking for throttling.
+    submit_rate: Arc<Mutex<SubmitRate>>,
+    /// Whether this client is exempt from submission throttling (opened with CAP_SYS_NICE).
+    throttle_exempt: bool,
+    /// Total number of commands submitted, see `FileStats`.
+    commands_submitted: AtomicU64,
+    /// Total number of bytes bound, see `FileStats`.
+    bytes_bound: AtomicU64,
+    _gpu_ref: gpu::HolderRef,
 }
 
 /// Convenience type alias for our DRM `File` type.
@@ -155,6 +358,71 @@ const VM_DRV_GPUFW_END: u64 = 0x61_ffffffff;
 /// Address of a special dummy page?
 const VM_UNK_PAGE: u64 = 0x6f_ffff8000;
 
+// All region boundaries must be aligned to the UAT page size, since gem_bind only accepts
+// page-aligned mappings and reports that page size to userspace.
+static_assert!(VM_SHADER_START as usize & mmu::UAT_PGMSK == 0);
+static_assert!((VM_SHADER_END + 1) as usize & mmu::UAT_PGMSK == 0);
+static_assert!(VM_USER_START as usize & mmu::UAT_PGMSK == 0);
+static_assert!((VM_USER_END + 1) as usize & mmu::UAT_PGMSK == 0);
+static_assert!(VM_DRV_GPU_START as usize & mmu::UAT_PGMSK == 0);
+static_assert!(VM_DRV_GPUFW_START as usize & mmu::UAT_PGMSK == 0);
+static_assert!(VM_UNK_PAGE as usize & mmu::UAT_PGMSK == 0);
+
+/// Returns whether `value` is a multiple of `page_size`, which must be a power of two.
+///
+/// Callers pass `mmu::UAT_PGSZ`, the page size userspace is told to align to. It is a parameter so
+/// that the checks can be exercised for page sizes other than the one the driver was built with.
+fn is_page_aligned(value: u64, page_size: usize) -> bool {
+    value & (page_size as u64 - 1) == 0
+}
+
+/// Check that a mapping of `range` bytes at `addr`, followed by `guard_size` bytes of guard
+/// pages, lies within a single VM region that userspace may bind into.
+///
+/// Compute-only VMs have no shader region, so only `user_range` is accepted for them.
+fn check_bind_range(
+    addr: u64,
+    range: u64,
+    guard_size: u64,
+    writable: bool,
+    compute_only: bool,
+    user_range: &core::ops::RangeInclusive<u64>,
+) -> Result {
+    let end = addr
+        .checked_add(range.checked_sub(1).ok_or(EINVAL)?)
+        .ok_or(EINVAL)?;
+    // Last address of the guard region, which must lie in the same VM region as the mapping.
+    let guard_end = end.checked_add(guard_size).ok_or(EINVAL)?;
+
+    if (VM_SHADER_START..=VM_SHADER_END).contains(&addr) {
+        if compute_only {
+            return Err(EINVAL); // Compute-only VMs have no shader region
+        }
+        // The shader region only holds code, which the GPU never needs to write. Refusing
+        // writable mappings there (W^X) turns stray shader heap writes into faults instead of
+        // silent code corruption.
+        if writable {
+            return Err(EINVAL);
+        }
+        if !(VM_SHADER_START..=VM_SHADER_END).contains(&guard_end) {
+            return Err(EINVAL); // Invalid map range
+        }
+    } else if user_range.contains(&addr) {
+        if !user_range.contains(&guard_end) {
+            return Err(EINVAL); // Invalid map range
+        }
+    } else {
+        return Err(EINVAL); // Invalid map range
+    }
+
+    // Just in case
+    if guard_end >= VM_DRV_GPU_START {
+        return Err(EINVAL);
+    }
+
+    Ok(())
+}
+
 impl drm::file::DriverFile for File {
     type Driver = driver::AsahiDriver;
 
@@ -170,6 +438,16 @@ impl drm::file::DriverFile for File {
             id,
             vms: xarray::XArray::new(xarray::flags::ALLOC1),
             queues: xarray::XArray::new(xarray::flags::ALLOC1),
+            perfmon: Arc::pin_init(Mutex::new(None))?,
+            submit_rate: Arc::pin_init(Mutex::new(SubmitRate {
+                window_start: time::ktime_get(),
+                count: 0,
+            }))?,
+            // SAFETY: `capable()` only checks the credentials of the current task.
+            throttle_exempt: unsafe { bindings::capable(bindings::CAP_SYS_NICE as i32) },
+            commands_submitted: AtomicU64::new(0),
+            bytes_bound: AtomicU64::new(0),
+            _gpu_ref: gpu.file_ref(),
         })?))
     }
 }
@@ -182,7 +460,7 @@ impl File {
     }
 
     #[allow(clippy::type_complexity)]
-    fn queues(self: Pin<&Self>) -> Pin<&xarray::XArray<Arc<Mutex<Box<dyn queue::Queue>>>>> {
+    fn queues(self: Pin<&Self>) -> Pin<&xarray::XArray<Box<Queue>>> {
         // SAFETY: Structural pinned projection for queues.
         // We never move out of this field.
         unsafe { self.map_unchecked(|s| &s.queues) }
@@ -237,6 +515,10 @@ impl File {
             max_commands_per_submission: MAX_COMMANDS_PER_SUBMISSION,
             max_commands_in_flight: MAX_COMMANDS_IN_FLIGHT,
             max_attachments: crate::microseq::MAX_ATTACHMENTS as u32,
+            num_queue_priorities: workqueue::num_priorities(),
+            num_mem_regions: gem::NUM_MEM_REGIONS,
+            max_vms_per_file: MAX_VMS_PER_FILE,
+            max_queues_per_file: MAX_QUEUES_PER_FILE,
 
             timer_frequency_hz: gpu.get_cfg().base_clock_hz,
             min_frequency_khz: gpu.get_dyncfg().pwr.min_frequency_khz(),
@@ -245,12 +527,23 @@ impl File {
 
             result_render_size: core::mem::size_of::<uapi::drm_asahi_result_render>() as u32,
             result_compute_size: core::mem::size_of::<uapi::drm_asahi_result_compute>() as u32,
+
+            firmware_version: [0; uapi::DRM_ASAHI_MAX_FW_VERSION_LEN as usize],
         };
 
         for (i, mask) in gpu.get_dyncfg().id.core_masks.iter().enumerate() {
             *(params.core_masks.get_mut(i).ok_or(EIO)?) = (*mask).try_into()?;
         }
 
+        // Versions with more components than the UAPI has room for are truncated.
+        for (dst, ver) in params
+            .firmware_version
+            .iter_mut()
+            .zip(device.data().fw_version.iter())
+        {
+            *dst = *ver;
+        }
+
         let size = core::mem::size_of::<uapi::drm_asahi_params_global>().min(data.size.try_into()?);
 
         // SAFETY: We only write to this userptr once, so there are no TOCTOU issues.
@@ -263,15 +556,175 @@ impl File {
         Ok(0)
     }
 
+    /// IOCTL: get_name: Get the GPU chip name (e.g. "G13G") as a NUL-terminated string.
+    ///
+    /// Follows the same convention as `DRM_IOCTL_GET_UNIQUE`: `size` is always updated to the
+    /// length of the name including the NUL terminator, and the name is only copied out if the
+    /// user buffer is large enough. Userspace can pass a size of 0 to query the length first.
+    pub(crate) fn get_name(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_get_name,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(device, "[File {}]: IOCTL: get_name\n", file.inner().id);
+
+        if data.extensions != 0 || data.flags != 0 {
+            return Err(EINVAL);
+        }
+
+        let gpu = &device.data().gpu;
+        let id = &gpu.get_dyncfg().id;
+        let name = CString::try_from_fmt(fmt!("{:?}{:?}", id.gpu_gen, id.gpu_variant))?;
+        let bytes = name.as_bytes_with_nul();
+
+        let user_size: usize = data.size.try_into()?;
+        data.size = bytes.len().try_into()?;
+
+        if user_size < bytes.len() {
+            return Ok(0);
+        }
+
+        // SAFETY: We only write to this userptr once, so there are no TOCTOU issues.
+        let mut writer =
+            unsafe { UserSlicePtr::new(data.pointer as usize as *mut _, bytes.len()).writer() };
+        writer.write_slice(bytes)?;
+
+        Ok(0)
+    }
+
+    /// IOCTL: trim: Release all cached memory in the kernel allocators.
+    ///
+    /// This is a privileged debugging aid, so test suites can get the allocators into a known
+    /// state and developers can reclaim kernel GPU memory without reloading the module.
+    pub(crate) fn trim(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_trim,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(device, "[File {}]: IOCTL: trim\n", file.inner().id);
+
+        if data.extensions != 0 || data.flags != 0 {
+            return Err(EINVAL);
+        }
+
+        device.data().gpu.trim_allocators()?;
+
+        Ok(0)
+    }
+
+    /// IOCTL: perfmon: Start, stop or read a performance counter session.
+    ///
+    /// Starting a session snapshots the firmware performance counters. Reading writes that
+    /// starting snapshot followed by a current snapshot to the user buffer, so userspace can
+    /// compute the counter deltas across the submissions it made in between. The counter layout
+    /// is firmware-specific and left for userspace to interpret. Reading or stopping without an
+    /// active session fails with ENOENT.
+    ///
+    /// The counters are global to the GPU and reveal the activity of every client, so this
+    /// requires `CAP_PERFMON` (or `CAP_SYS_ADMIN`), like other system-wide performance monitoring
+    /// interfaces. Otherwise it fails with EACCES.
+    pub(crate) fn perfmon(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_perfmon,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {}]: IOCTL: perfmon op={}\n",
+            file.inner().id,
+            data.op
+        );
+
+        if data.extensions != 0 {
+            return Err(EINVAL);
+        }
+
+        // SAFETY: `capable()` only checks the credentials of the current task.
+        let perfmon_capable = unsafe {
+            bindings::capable(bindings::CAP_PERFMON as i32)
+                || bindings::capable(bindings::CAP_SYS_ADMIN as i32)
+        };
+        if !perfmon_capable {
+            return Err(EACCES);
+        }
+
+        let gpu = &device.data().gpu;
+        let mut session = file.inner().perfmon.lock();
+
+        match data.op {
+            uapi::drm_asahi_perfmon_op_ASAHI_PERFMON_OP_START => {
+                *session = Some(gpu.perf_counters()?);
+            }
+            uapi::drm_asahi_perfmon_op_ASAHI_PERFMON_OP_STOP => {
+                if session.take().is_none() {
+                    return Err(ENOENT);
+                }
+            }
+            uapi::drm_asahi_perfmon_op_ASAHI_PERFMON_OP_READ => {
+                let start = session.as_ref().ok_or(ENOENT)?;
+                let current = gpu.perf_counters()?;
+                let size = start.len() + current.len();
+
+                // Report the required size if the buffer is too small.
+                if (data.size as usize) < size {
+                    data.size = size.try_into()?;
+                    return Err(ENOSPC);
+                }
+                data.size = size.try_into()?;
+
+                // SAFETY: We only write to this userptr once, so there are no TOCTOU issues.
+                let mut writer =
+                    unsafe { UserSlicePtr::new(data.pointer as usize as *mut _, size).writer() };
+                writer.write_slice(start)?;
+                writer.write_slice(&current)?;
+            }
+            _ => return Err(EINVAL),
+        }
+
+        Ok(0)
+    }
+
     /// IOCTL: vm_create: Create a new `Vm`.
+    ///
+    /// If `ASAHI_VM_COMPUTE_ONLY` is set, the VM is intended for compute-only clients which never
+    /// use the USC shader address space. `gem_bind` then only accepts mappings within the general
+    /// user region, and binds into the shader region fail with EINVAL.
+    ///
+    /// Userspace may request a custom general user region with `user_start`/`user_end`, e.g. to
+    /// mirror another API's fixed address space layout. If both are zero, the default
+    /// `VM_USER_START..=VM_USER_END` region is used and reported back.
+    ///
+    /// Returns ENOSPC if the client already has `MAX_VMS_PER_FILE` VMs.
     pub(crate) fn vm_create(
         device: &AsahiDevice,
         data: &mut uapi::drm_asahi_vm_create,
         file: &DrmFile,
     ) -> Result<u32> {
-        if data.extensions != 0 {
+        if data.extensions != 0 || (data.flags & !uapi::ASAHI_VM_COMPUTE_ONLY) != 0 {
+            return Err(EINVAL);
+        }
+
+        let compute_only = data.flags & uapi::ASAHI_VM_COMPUTE_ONLY != 0;
+
+        // A custom user range must be page aligned, start above the null page, end below the
+        // driver-managed regions, and not overlap the shader region.
+        if data.user_start == 0 && data.user_end == 0 {
+            data.user_start = VM_USER_START;
+            data.user_end = VM_USER_END;
+        } else if data.user_start >= data.user_end
+            || data.user_end >= VM_DRV_GPU_START
+            || !is_page_aligned(data.user_start | (data.user_end + 1), mmu::UAT_PGSZ)
+            || data.user_start < mmu::IOVA_USER_BASE as u64
+            || (data.user_start <= VM_SHADER_END && data.user_end >= VM_SHADER_START)
+        {
             return Err(EINVAL);
         }
+        let user_range = data.user_start..=data.user_end;
+
+        // This is a soft limit: concurrent vm_create calls on the same file may race past it.
+        if file.inner().vms().count() >= MAX_VMS_PER_FILE as usize {
+            return Err(ENOSPC);
+        }
 
         let gpu = &device.data().gpu;
         let file_id = file.inner().id;
@@ -280,13 +733,26 @@ impl File {
         let resv = file.inner().vms().reserve()?;
         let id: u32 = resv.index().try_into()?;
 
-        mod_dev_dbg!(device, "[File {} VM {}]: VM Create\n", file_id, id);
+        // VM handles are only unique within a file, so also log the device-global VM ID that
+        // lower layers (MMU, allocators, queues) use, to correlate their output with this file.
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: VM Create (global VM ID {}, compute_only={}, user {:#x?})\n",
+            file_id,
+            id,
+            vm.id(),
+            compute_only,
+            user_range
+        );
         mod_dev_dbg!(
             device,
             "[File {} VM {}]: Creating allocators\n",
             file_id,
             id
         );
+        // Compute-only VMs need both allocators too, since every queue created on a VM takes
+        // both. This is cheap: the allocators only reserve their VA ranges here, and do not
+        // allocate any backing memory until the first allocation from them.
         let ualloc = Arc::pin_init(Mutex::new(alloc::DefaultAllocator::new(
             device,
             &vm,
@@ -318,9 +784,9 @@ impl File {
             file_id,
             id
         );
-        let mut dummy_obj = gem::new_kernel_object(device, 0x4000)?;
+        let mut dummy_obj = gem::new_kernel_object(device, mmu::UAT_PGSZ)?;
         dummy_obj.vmap()?.as_mut_slice().fill(0);
-        dummy_obj.map_at(&vm, VM_UNK_PAGE, mmu::PROT_GPU_SHARED_RW, true)?;
+        dummy_obj.map_at(&vm, VM_UNK_PAGE, mmu::PROT_GPU_SHARED_RW, 1)?;
 
         mod_dev_dbg!(device, "[File {} VM {}]: VM created\n", file_id, id);
         resv.store(Box::try_new(Vm {
@@ -328,6 +794,10 @@ impl File {
             ualloc_priv,
             vm,
             dummy_obj,
+            compute_only,
+            user_range,
+            bound_objs: Arc::pin_init(Mutex::new(BoundObjects::new()?))?,
+            queue_count: Arc::try_new(AtomicU32::new(0))?,
         })?)?;
 
         data.vm_id = id;
@@ -336,8 +806,13 @@ impl File {
     }
 
     /// IOCTL: vm_destroy: Destroy a `Vm`.
+    ///
+    /// Returns EBUSY while queues created on this `Vm` still exist. Userspace must destroy all
+    /// queues created on a `Vm` before destroying the `Vm` itself. The check is advisory: a
+    /// `queue_create` racing with `vm_destroy` on the same file may still succeed, and the queue
+    /// then keeps its own references to the `Vm`'s address space and allocators.
     pub(crate) fn vm_destroy(
-        _device: &AsahiDevice,
+        device: &AsahiDevice,
         data: &mut uapi::drm_asahi_vm_destroy,
         file: &DrmFile,
     ) -> Result<u32> {
@@ -345,6 +820,43 @@ impl File {
             return Err(EINVAL);
         }
 
+        // Clone it immediately so we aren't holding the XArray lock
+        let (vm, queue_count) = {
+            let file_vm = file
+                .inner()
+                .vms()
+                .get(data.vm_id.try_into()?)
+                .ok_or(ENOENT)?;
+            let file_vm = file_vm.borrow();
+            (
+                file_vm.vm.clone(),
+                file_vm.queue_count.load(Ordering::Relaxed),
+            )
+        };
+        if queue_count != 0 {
+            return Err(EBUSY);
+        }
+
+        // Flush the VM's translations out of its UAT slot now instead of whenever the last
+        // mapping goes away, so a VM later assigned the same slot cannot hit stale TLB entries.
+        // This is best effort: the destroy proceeds regardless, and the slot is still released
+        // when the VM is finally dropped.
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: Releasing UAT slot\n",
+            file.inner().id,
+            data.vm_id
+        );
+        if let Err(e) = vm.release_slot() {
+            dev_warn!(
+                device,
+                "[File {} VM {}]: Failed to release UAT slot on destroy: {:?}\n",
+                file.inner().id,
+                data.vm_id,
+                e
+            );
+        }
+
         if file.inner().vms().remove(data.vm_id as usize).is_none() {
             Err(ENOENT)
         } else {
@@ -352,6 +864,44 @@ impl File {
         }
     }
 
+    /// IOCTL: vm_info: Report the user address range of a `Vm` and how much of it is mapped.
+    ///
+    /// The mapped byte count covers every live mapping in the `Vm`, including the driver's own
+    /// allocations outside the user range, so it is only a coarse usage hint.
+    pub(crate) fn vm_info(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_vm_info,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: IOCTL: vm_info\n",
+            file.inner().id,
+            data.vm_id
+        );
+
+        if data.extensions != 0 || data.flags != 0 {
+            return Err(EINVAL);
+        }
+
+        // Clone it immediately so we aren't holding the XArray lock
+        let (vm, user_range) = {
+            let file_vm = file
+                .inner()
+                .vms()
+                .get(data.vm_id.try_into()?)
+                .ok_or(ENOENT)?;
+            let file_vm = file_vm.borrow();
+            (file_vm.vm.clone(), file_vm.user_range.clone())
+        };
+
+        data.user_start = *user_range.start();
+        data.user_end = *user_range.end();
+        data.mapped_bytes = vm.mapped_bytes().try_into()?;
+
+        Ok(0)
+    }
+
     /// IOCTL: gem_create: Create a new GEM object.
     pub(crate) fn gem_create(
         device: &AsahiDevice,
@@ -360,14 +910,16 @@ impl File {
     ) -> Result<u32> {
         mod_dev_dbg!(
             device,
-            "[File {}]: IOCTL: gem_create size={:#x?}\n",
+            "[File {}]: IOCTL: gem_create size={:#x?} region={}\n",
             file.inner().id,
-            data.size
+            data.size,
+            data.region
         );
 
         if data.extensions != 0
             || (data.flags & !(uapi::ASAHI_GEM_WRITEBACK | uapi::ASAHI_GEM_VM_PRIVATE)) != 0
             || (data.flags & uapi::ASAHI_GEM_VM_PRIVATE == 0 && data.vm_id != 0)
+            || data.region >= gem::NUM_MEM_REGIONS
         {
             return Err(EINVAL);
         }
@@ -386,7 +938,13 @@ impl File {
             None
         };
 
-        let bo = gem::new_object(device, data.size.try_into()?, data.flags, vm_id)?;
+        let bo = gem::new_object(
+            device,
+            data.size.try_into()?,
+            data.flags,
+            vm_id,
+            data.region,
+        )?;
 
         let handle = bo.gem.create_handle(file)?;
         data.handle = handle;
@@ -402,7 +960,98 @@ impl File {
         Ok(0)
     }
 
+    /// IOCTL: gem_create_bind: Create a new GEM object and bind all of it into a Vm.
+    ///
+    /// This is equivalent to `gem_create` followed by a `gem_bind` of the whole object at `addr`,
+    /// with the same validation, but takes a single round trip. With `ASAHI_GEM_VM_PRIVATE`, the
+    /// object is private to the target Vm.
+    ///
+    /// The object is bound before its handle is created. If the bind fails, the object is freed
+    /// and no handle is returned, so a failed call has no side effects.
+    pub(crate) fn gem_create_bind(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_create_bind,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: IOCTL: gem_create_bind size={:#x?} region={} bind_flags={:#x?} -> {:#x?} guard={}\n",
+            file.inner().id,
+            data.vm_id,
+            data.size,
+            data.region,
+            data.bind_flags,
+            data.addr,
+            data.guard_pages
+        );
+
+        if data.extensions != 0
+            || (data.flags & !(uapi::ASAHI_GEM_WRITEBACK | uapi::ASAHI_GEM_VM_PRIVATE)) != 0
+            || data.region >= gem::NUM_MEM_REGIONS
+        {
+            return Err(EINVAL);
+        }
+
+        let target = Self::bind_target(file, data.vm_id)?;
+        let vm_id = if data.flags & uapi::ASAHI_GEM_VM_PRIVATE != 0 {
+            Some(target.vm.id())
+        } else {
+            None
+        };
+
+        let bo = gem::new_object(
+            device,
+            data.size.try_into()?,
+            data.flags,
+            vm_id,
+            data.region,
+        )?;
+
+        let op = BindOp {
+            handle: 0,
+            flags: data.bind_flags,
+            offset: 0,
+            range: bo.size().try_into()?,
+            addr: data.addr,
+            guard_pages: data.guard_pages,
+        };
+
+        let mut bound_objs = target.bound_objs.lock();
+        let mut bound = Self::bind_object(device, file, &target, &op, bo)?;
+        let iova = bound.addr as u64;
+        Self::register_bound(
+            file,
+            &target,
+            &mut bound_objs,
+            core::slice::from_mut(&mut bound),
+        )?;
+        core::mem::drop(bound_objs);
+
+        data.handle = match bound.bo.gem.create_handle(file) {
+            Ok(handle) => handle,
+            Err(e) => {
+                Self::unbind_all_of(&target, core::slice::from_mut(&mut bound));
+                target.bound_objs.lock().remove_all(bound.bo.id());
+                return Err(e);
+            }
+        };
+        data.iova = iova;
+
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: IOCTL: gem_create_bind handle={:#x?} iova={:#x?}\n",
+            file.inner().id,
+            data.vm_id,
+            data.handle,
+            data.iova
+        );
+
+        Ok(0)
+    }
+
     /// IOCTL: gem_mmap_offset: Assign an mmap offset to a GEM object.
+    ///
+    /// Also reports the CPU caching mode (write-back or write-combined) applied to the object.
     pub(crate) fn gem_mmap_offset(
         device: &AsahiDevice,
         data: &mut uapi::drm_asahi_gem_mmap_offset,
@@ -421,108 +1070,475 @@ impl File {
 
         let bo = gem::lookup_handle(file, data.handle)?;
         data.offset = bo.gem.create_mmap_offset()?;
+        data.caching = if bo.is_writeback() {
+            uapi::ASAHI_GEM_CACHING_WRITEBACK
+        } else {
+            uapi::ASAHI_GEM_CACHING_WRITECOMBINE
+        };
         Ok(0)
     }
 
-    /// IOCTL: gem_bind: Map or unmap a GEM object into a Vm.
-    pub(crate) fn gem_bind(
+    /// IOCTL: gem_get_iova: Report the GPU address a GEM object is mapped at in a `Vm`.
+    ///
+    /// Returns `ENOENT` if the `Vm` does not exist or the object is not mapped into it.
+    pub(crate) fn gem_get_iova(
         device: &AsahiDevice,
-        data: &mut uapi::drm_asahi_gem_bind,
+        data: &mut uapi::drm_asahi_gem_get_iova,
         file: &DrmFile,
     ) -> Result<u32> {
         mod_dev_dbg!(
             device,
-            "[File {} VM {}]: IOCTL: gem_bind op={:?} handle={:#x?} flags={:#x?} {:#x?}:{:#x?} -> {:#x?}\n",
+            "[File {} VM {}]: IOCTL: gem_get_iova handle={:#x?}\n",
             file.inner().id,
             data.vm_id,
-            data.op,
-            data.handle,
-            data.flags,
-            data.offset,
-            data.range,
-            data.addr
+            data.handle
         );
 
-        if data.extensions != 0 {
+        if data.extensions != 0 || data.flags != 0 {
             return Err(EINVAL);
         }
 
-        match data.op {
-            uapi::drm_asahi_bind_op_ASAHI_BIND_OP_BIND => Self::do_gem_bind(device, data, file),
-            uapi::drm_asahi_bind_op_ASAHI_BIND_OP_UNBIND => Err(ENOTSUPP),
-            uapi::drm_asahi_bind_op_ASAHI_BIND_OP_UNBIND_ALL => {
-                Self::do_gem_unbind_all(device, data, file)
-            }
-            _ => Err(EINVAL),
-        }
+        // Only grab the VM ID so we aren't holding the XArray lock
+        let vm_id = file
+            .inner()
+            .vms()
+            .get(data.vm_id.try_into()?)
+            .ok_or(ENOENT)?
+            .borrow()
+            .vm
+            .id();
+
+        let bo = gem::lookup_handle(file, data.handle)?;
+        data.iova = bo.iova(vm_id).ok_or(ENOENT)?.try_into()?;
+
+        Ok(0)
     }
 
-    pub(crate) fn do_gem_bind(
-        _device: &AsahiDevice,
-        data: &mut uapi::drm_asahi_gem_bind,
+    /// IOCTL: gem_set_label: Set or clear the debug label of a GEM object.
+    ///
+    /// The label is a UTF-8 string of at most `gem::MAX_LABEL_LEN` bytes without a NUL
+    /// terminator, and is shown in driver debug logs for the object. A size of 0 clears it.
+    pub(crate) fn gem_set_label(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_set_label,
         file: &DrmFile,
     ) -> Result<u32> {
-        if data.offset != 0 {
-            return Err(EINVAL); // Not supported yet
-        }
-
-        if (data.addr | data.range) as usize & mmu::UAT_PGMSK != 0 {
-            return Err(EINVAL); // Must be page aligned
+        mod_dev_dbg!(
+            device,
+            "[File {}]: IOCTL: gem_set_label handle={:#x?} size={}\n",
+            file.inner().id,
+            data.handle,
+            data.label_size
+        );
+
+        if data.extensions != 0 || data.flags != 0 {
+            return Err(EINVAL);
         }
 
-        if (data.flags & !(uapi::ASAHI_BIND_READ | uapi::ASAHI_BIND_WRITE)) != 0 {
+        let size: usize = data.label_size.try_into()?;
+        if size > gem::MAX_LABEL_LEN {
+            return Err(EINVAL);
+        }
+
+        let bo = gem::lookup_handle(file, data.handle)?;
+
+        let label = if size == 0 {
+            None
+        } else {
+            // SAFETY: We only read this once, so there are no TOCTOU issues.
+            let bytes =
+                unsafe { UserSlicePtr::new(data.label as usize as *mut _, size).read_all()? };
+            let label = core::str::from_utf8(&bytes).map_err(|_| EINVAL)?;
+            if label.contains('\0') {
+                return Err(EINVAL);
+            }
+            Some(CString::try_from_fmt(fmt!("{}", label))?)
+        };
+
+        bo.set_label(label);
+        Ok(0)
+    }
+
+    /// IOCTL: gem_populate: Allocate all the backing pages of a GEM object up front.
+    ///
+    /// This lets userspace avoid demand paging stalls on the first submission that touches a
+    /// freshly allocated object.
+    pub(crate) fn gem_populate(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_populate,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {}]: IOCTL: gem_populate handle={:#x?} flags={:#x?}\n",
+            file.inner().id,
+            data.handle,
+            data.flags
+        );
+
+        if data.extensions != 0 || (data.flags & !uapi::ASAHI_POPULATE_ZERO) != 0 {
             return Err(EINVAL);
         }
 
         let mut bo = gem::lookup_handle(file, data.handle)?;
+        bo.populate(data.flags & uapi::ASAHI_POPULATE_ZERO != 0)?;
+
+        Ok(0)
+    }
+
+    /// IOCTL: gem_busy: Query whether a GEM object may be in use by the GPU, without blocking.
+    pub(crate) fn gem_busy(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_busy,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {}]: IOCTL: gem_busy handle={:#x?}\n",
+            file.inner().id,
+            data.handle
+        );
+
+        if data.extensions != 0 {
+            return Err(EINVAL);
+        }
 
-        if data.range != bo.size().try_into()? {
-            return Err(EINVAL); // Not supported yet
+        let bo = gem::lookup_handle(file, data.handle)?;
+        let (read_busy, write_busy) = bo.busy();
+
+        data.busy = 0;
+        if read_busy {
+            data.busy |= uapi::ASAHI_BUSY_READ;
+        }
+        if write_busy {
+            data.busy |= uapi::ASAHI_BUSY_WRITE;
         }
 
-        let start = data.addr;
-        let end = data.addr + data.range - 1;
+        Ok(0)
+    }
 
-        if (VM_SHADER_START..=VM_SHADER_END).contains(&start) {
-            if !(VM_SHADER_START..=VM_SHADER_END).contains(&end) {
-                return Err(EINVAL); // Invalid map range
+    /// IOCTL: gem_bind: Map or unmap a GEM object into a Vm.
+    pub(crate) fn gem_bind(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_bind,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: IOCTL: gem_bind op={:?} handle={:#x?} flags={:#x?} {:#x?}:{:#x?} -> {:#x?} guard={}\n",
+            file.inner().id,
+            data.vm_id,
+            data.op,
+            data.handle,
+            data.flags,
+            data.offset,
+            data.range,
+            data.addr,
+            data.guard_pages
+        );
+
+        if data.extensions != 0 {
+            return Err(EINVAL);
+        }
+
+        match data.op {
+            uapi::drm_asahi_bind_op_ASAHI_BIND_OP_BIND => Self::do_gem_bind(device, data, file),
+            uapi::drm_asahi_bind_op_ASAHI_BIND_OP_UNBIND => Self::do_gem_unbind(device, data, file),
+            uapi::drm_asahi_bind_op_ASAHI_BIND_OP_UNBIND_ALL => {
+                Self::do_gem_unbind_all(device, data, file)
             }
-        } else if (VM_USER_START..=VM_USER_END).contains(&start) {
-            if !(VM_USER_START..=VM_USER_END).contains(&end) {
-                return Err(EINVAL); // Invalid map range
+            _ => Err(EINVAL),
+        }
+    }
+
+    pub(crate) fn do_gem_bind(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_bind,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        let target = Self::bind_target(file, data.vm_id)?;
+        let op = BindOp {
+            handle: data.handle,
+            flags: data.flags,
+            offset: data.offset,
+            range: data.range,
+            addr: data.addr,
+            guard_pages: data.guard_pages,
+        };
+
+        let mut bound_objs = target.bound_objs.lock();
+        let mut bound = Self::bind_one(device, file, &target, &op)?;
+        Self::register_bound(
+            file,
+            &target,
+            &mut bound_objs,
+            core::slice::from_mut(&mut bound),
+        )?;
+
+        Ok(0)
+    }
+
+    /// IOCTL: gem_bind_batch: Map several GEM objects into a Vm in one call.
+    ///
+    /// Each operation is validated like a single `gem_bind`. The `Vm`'s registry lock is held
+    /// for the whole batch, so other bind and unbind operations on the `Vm` never see it half
+    /// applied. If any operation fails, all mappings created by earlier operations in the batch
+    /// are removed again before the lock is released, and `failed_index` is set to the index of
+    /// the failing operation (or `u32::MAX` if the failure is not specific to one operation).
+    ///
+    /// This does not make the batch atomic for the GPU: work already running on the `Vm` may
+    /// observe the mappings one at a time.
+    pub(crate) fn gem_bind_batch(
+        device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_bind_batch,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: IOCTL: gem_bind_batch count={}\n",
+            file.inner().id,
+            data.vm_id,
+            data.op_count
+        );
+
+        data.failed_index = u32::MAX;
+
+        if data.extensions != 0
+            || data.flags != 0
+            || data.op_count == 0
+            || data.op_count > MAX_BINDS_PER_BATCH
+        {
+            return Err(EINVAL);
+        }
+
+        let target = Self::bind_target(file, data.vm_id)?;
+
+        const STRIDE: usize = core::mem::size_of::<uapi::drm_asahi_gem_bind_op>();
+        let size = STRIDE * data.op_count as usize;
+
+        // SAFETY: We only read this once, so there are no TOCTOU issues.
+        let mut reader = unsafe { UserSlicePtr::new(data.ops as usize as *mut _, size).reader() };
+
+        let mut bound: Vec<BoundObject> = Vec::try_with_capacity(data.op_count as usize)?;
+
+        let mut bound_objs = target.bound_objs.lock();
+        for i in 0..data.op_count {
+            let ret = Self::read_bind_op(&mut reader)
+                .and_then(|op| Self::bind_one(device, file, &target, &op));
+
+            match ret {
+                // Cannot fail, we reserved `op_count` entries above.
+                Ok(b) => bound.try_push(b)?,
+                Err(e) => {
+                    mod_dev_dbg!(
+                        device,
+                        "[File {} VM {}]: IOCTL: gem_bind_batch op {} failed: {:?}\n",
+                        file.inner().id,
+                        data.vm_id,
+                        i,
+                        e
+                    );
+                    data.failed_index = i;
+                    Self::unbind_all_of(&target, &mut bound);
+                    return Err(e);
+                }
             }
-        } else {
-            return Err(EINVAL); // Invalid map range
         }
 
-        // Just in case
-        if end >= VM_DRV_GPU_START {
+        Self::register_bound(file, &target, &mut bound_objs, &mut bound)?;
+
+        Ok(0)
+    }
+
+    /// Read one `drm_asahi_gem_bind_op` from a user buffer.
+    fn read_bind_op(reader: &mut UserSlicePtrReader) -> Result<BindOp> {
+        const STRIDE: usize = core::mem::size_of::<uapi::drm_asahi_gem_bind_op>();
+        let mut op: MaybeUninit<uapi::drm_asahi_gem_bind_op> = MaybeUninit::uninit();
+
+        // SAFETY: The size of `op` is STRIDE
+        unsafe { reader.read_raw(op.as_mut_ptr() as *mut u8, STRIDE)? };
+
+        // SAFETY: All bit patterns in the struct are valid
+        let op = unsafe { op.assume_init() };
+
+        Ok(BindOp {
+            handle: op.handle,
+            flags: op.flags,
+            offset: op.offset,
+            range: op.range,
+            addr: op.addr,
+            guard_pages: op.guard_pages,
+        })
+    }
+
+    /// Look up the `Vm` a bind operation targets, cloning out what is needed so the XArray lock
+    /// is not held while mapping.
+    fn bind_target(file: &DrmFile, vm_id: u32) -> Result<BindTarget> {
+        let file_vm = file.inner().vms().get(vm_id.try_into()?).ok_or(ENOENT)?;
+        let file_vm = file_vm.borrow();
+        Ok(BindTarget {
+            vm: file_vm.vm.clone(),
+            compute_only: file_vm.compute_only,
+            user_range: file_vm.user_range.clone(),
+            bound_objs: file_vm.bound_objs.clone(),
+        })
+    }
+
+    /// Look up the object of a single bind operation and map it into the target `Vm`.
+    fn bind_one(
+        device: &AsahiDevice,
+        file: &DrmFile,
+        target: &BindTarget,
+        op: &BindOp,
+    ) -> Result<BoundObject> {
+        let bo = gem::lookup_handle(file, op.handle)?;
+        Self::bind_object(device, file, target, op, bo)
+    }
+
+    /// Validate a single bind operation and map a given object into the target `Vm`. The
+    /// operation's handle is ignored.
+    ///
+    /// Mappings in the shader region must be read-only.
+    fn bind_object(
+        device: &AsahiDevice,
+        file: &DrmFile,
+        target: &BindTarget,
+        op: &BindOp,
+        mut bo: gem::ObjectRef,
+    ) -> Result<BoundObject> {
+        if !is_page_aligned(op.addr | op.offset | op.range, mmu::UAT_PGSZ) {
+            return Err(EINVAL); // Must be page aligned
+        }
+
+        if (op.flags & !(uapi::ASAHI_BIND_READ | uapi::ASAHI_BIND_WRITE)) != 0 {
             return Err(EINVAL);
         }
 
-        let prot = if data.flags & uapi::ASAHI_BIND_READ != 0 {
-            if data.flags & uapi::ASAHI_BIND_WRITE != 0 {
+        let offset: usize = op.offset.try_into()?;
+        let range: usize = op.range.try_into()?;
+        if range == 0 || offset.checked_add(range).ok_or(EINVAL)? > bo.size() {
+            return Err(EINVAL); // Must be a non-empty range within the object
+        }
+
+        // A zero guard size selects the default of a single guard page.
+        let guard_pages = match op.guard_pages {
+            0 => 1,
+            n if n <= MAX_GUARD_PAGES => n,
+            _ => return Err(EINVAL),
+        };
+
+        let start = op.addr;
+        check_bind_range(
+            start,
+            op.range,
+            guard_pages as u64 * mmu::UAT_PGSZ as u64,
+            op.flags & uapi::ASAHI_BIND_WRITE != 0,
+            target.compute_only,
+            &target.user_range,
+        )?;
+
+        let prot = if op.flags & uapi::ASAHI_BIND_READ != 0 {
+            if op.flags & uapi::ASAHI_BIND_WRITE != 0 {
                 mmu::PROT_GPU_SHARED_RW
             } else {
                 mmu::PROT_GPU_SHARED_RO
             }
-        } else if data.flags & uapi::ASAHI_BIND_WRITE != 0 {
+        } else if op.flags & uapi::ASAHI_BIND_WRITE != 0 {
             mmu::PROT_GPU_SHARED_WO
         } else {
             return Err(EINVAL); // Must specify one of ASAHI_BIND_{READ,WRITE}
         };
 
-        // Clone it immediately so we aren't holding the XArray lock
-        let vm = file
-            .inner()
-            .vms()
-            .get(data.vm_id.try_into()?)
-            .ok_or(ENOENT)?
-            .borrow()
-            .vm
-            .clone();
+        bo.map_range_at(&target.vm, start, offset, range, prot, guard_pages as usize)?;
+
+        mod_dev_dbg!(
+            device,
+            "[File {} VM {}]: Bound object {} ({}) at {:#x?}:{:#x?}\n",
+            file.inner().id,
+            target.vm.id(),
+            bo.id(),
+            bo.label(),
+            start,
+            range
+        );
+
+        Ok(BoundObject {
+            bo,
+            addr: start.try_into()?,
+            range,
+        })
+    }
+
+    /// Remove the mappings created for a set of bind operations that are being rolled back.
+    fn unbind_all_of(target: &BindTarget, bound: &mut [BoundObject]) {
+        let vm_id = target.vm.id();
+        for b in bound.iter_mut() {
+            b.bo.drop_new_mapping_at(vm_id, b.addr);
+        }
+    }
+
+    /// Add freshly bound objects to the target `Vm`'s registry and account for them, rolling
+    /// back their mappings if the registry cannot grow.
+    ///
+    /// `bound_objs` is the locked registry of `target`, which must have been held since the
+    /// objects were bound.
+    fn register_bound(
+        file: &DrmFile,
+        target: &BindTarget,
+        bound_objs: &mut BoundObjects,
+        bound: &mut [BoundObject],
+    ) -> Result {
+        let mut added = 0;
+        let mut ret = Ok(());
+        for b in bound.iter() {
+            ret = bound_objs.add(&b.bo);
+            if ret.is_err() {
+                break;
+            }
+            added += 1;
+        }
+
+        if let Err(e) = ret {
+            for b in bound[..added].iter() {
+                bound_objs.remove_one(b.bo.id());
+            }
+            Self::unbind_all_of(target, bound);
+            return Err(e);
+        }
+
+        for b in bound.iter() {
+            file.inner()
+                .bytes_bound
+                .fetch_add(b.range as u64, Ordering::Relaxed);
+        }
+
+        Ok(())
+    }
+
+    /// Unmap a GEM object from a given address in a `Vm`.
+    ///
+    /// The whole mapping at `addr` is removed, so `range` and `offset` must be zero. Unbinding is
+    /// refused with EBUSY while the `Vm` is bound for GPU work, since that work may still be
+    /// accessing the mapping.
+    pub(crate) fn do_gem_unbind(
+        _device: &AsahiDevice,
+        data: &mut uapi::drm_asahi_gem_bind,
+        file: &DrmFile,
+    ) -> Result<u32> {
+        if data.flags != 0 || data.offset != 0 || data.range != 0 || data.guard_pages != 0 {
+            return Err(EINVAL);
+        }
+
+        if !is_page_aligned(data.addr, mmu::UAT_PGSZ) {
+            return Err(EINVAL); // Must be page aligned
+        }
+
+        let mut bo = gem::lookup_handle(file, data.handle)?;
+        let target = Self::bind_target(file, data.vm_id)?;
 
-        bo.map_at(&vm, start, prot, true)?;
+        let mut bound_objs = target.bound_objs.lock();
+        bo.drop_mapping_at(target.vm.id(), data.addr.try_into()?)?;
+        bound_objs.remove_one(bo.id());
 
         Ok(0)
     }
@@ -532,7 +1548,12 @@ impl File {
         data: &mut uapi::drm_asahi_gem_bind,
         file: &DrmFile,
     ) -> Result<u32> {
-        if data.flags != 0 || data.offset != 0 || data.range != 0 || data.addr != 0 {
+        if data.flags != 0
+            || data.offset != 0
+            || data.range != 0
+            || data.addr != 0
+            || data.guard_pages != 0
+        {
             return Err(EINVAL);
         }
 
@@ -540,22 +1561,20 @@ impl File {
 
         if data.vm_id == 0 {
             bo.drop_file_mappings(file.inner().id);
+            file.inner().forget_bound(bo.id());
         } else {
-            let vm_id = file
-                .inner()
-                .vms()
-                .get(data.vm_id.try_into()?)
-                .ok_or(ENOENT)?
-                .borrow()
-                .vm
-                .id();
-            bo.drop_vm_mappings(vm_id);
+            let target = Self::bind_target(file, data.vm_id)?;
+            let mut bound_objs = target.bound_objs.lock();
+            bo.drop_vm_mappings(target.vm.id());
+            bound_objs.remove_all(bo.id());
         }
 
         Ok(0)
     }
 
     /// IOCTL: queue_create: Create a new command submission queue of a given type.
+    ///
+    /// Returns ENOSPC if the client already has `MAX_QUEUES_PER_FILE` queues.
     pub(crate) fn queue_create(
         device: &AsahiDevice,
         data: &mut uapi::drm_asahi_queue_create,
@@ -575,7 +1594,7 @@ impl File {
 
         if data.extensions != 0
             || data.flags != 0
-            || data.priority > 3
+            || data.priority >= workqueue::num_priorities()
             || data.queue_caps == 0
             || (data.queue_caps
                 & !(uapi::drm_asahi_queue_cap_DRM_ASAHI_QUEUE_CAP_RENDER
@@ -586,7 +1605,8 @@ impl File {
             return Err(EINVAL);
         }
 
-        let resv = file.inner().queues().reserve()?;
+        // Lock ordering: look up the VM (and drop the vms lock) before touching the queues
+        // XArray. See the `File` documentation.
         let file_vm = file
             .inner()
             .vms()
@@ -595,9 +1615,25 @@ impl File {
         let vm = file_vm.borrow().vm.clone();
         let ualloc = file_vm.borrow().ualloc.clone();
         let ualloc_priv = file_vm.borrow().ualloc_priv.clone();
+        let compute_only = file_vm.borrow().compute_only;
+        let vm_ref = VmQueueRef::new(&file_vm.borrow().queue_count);
         // Drop the vms lock eagerly
         core::mem::drop(file_vm);
 
+        // Render queues need the shader region, which compute-only VMs do not have.
+        if compute_only
+            && data.queue_caps & uapi::drm_asahi_queue_cap_DRM_ASAHI_QUEUE_CAP_RENDER != 0
+        {
+            return Err(EINVAL);
+        }
+
+        // This is a soft limit: concurrent queue_create calls on the same file may race past it.
+        if file.inner().queues().count() >= MAX_QUEUES_PER_FILE as usize {
+            return Err(ENOSPC);
+        }
+
+        let resv = file.inner().queues().reserve()?;
+
         let queue =
             device
                 .data()
@@ -605,7 +1641,11 @@ impl File {
                 .new_queue(vm, ualloc, ualloc_priv, data.priority, data.queue_caps)?;
 
         data.queue_id = resv.index().try_into()?;
-        resv.store(Arc::pin_init(Mutex::new(queue))?)?;
+        resv.store(Box::try_new(Queue {
+            queue: Arc::pin_init(Mutex::new(queue))?,
+            _vm_ref: vm_ref,
+            _gpu_ref: device.data().gpu.queue_ref(),
+        })?)?;
 
         Ok(0)
     }
@@ -632,36 +1672,84 @@ impl File {
         }
     }
 
+    /// Account for a new submission, enforcing the optional per-client rate limit.
+    ///
+    /// Returns EAGAIN if the client exceeded the `submit_rate_limit` module parameter within the
+    /// current one-second window, so a single client cannot monopolize the GPU.
+    fn throttle(&self) -> Result {
+        let limit = {
+            let lock = crate::THIS_MODULE.kernel_param_lock();
+            *crate::submit_rate_limit.read(&lock)
+        };
+
+        if limit == 0 || self.throttle_exempt {
+            return Ok(());
+        }
+
+        let now = time::ktime_get();
+        let mut rate = self.submit_rate.lock();
+
+        if now - rate.window_start >= Duration::from_secs(1) {
+            rate.window_start = now;
+            rate.count = 0;
+        }
+
+        if rate.count >= limit {
+            mod_pr_debug!("[File {}]: Submission rate limit exceeded\n", self.id);
+            return Err(EAGAIN);
+        }
+
+        rate.count += 1;
+        Ok(())
+    }
+
     /// IOCTL: submit: Submit GPU work to a command submission queue.
+    ///
+    /// The driver-assigned submission ID is returned in `submission_id`. Kernel log lines for
+    /// this submission carry the same ID.
     pub(crate) fn submit(
         device: &AsahiDevice,
         data: &mut uapi::drm_asahi_submit,
         file: &DrmFile,
     ) -> Result<u32> {
         if data.extensions != 0
-            || data.flags != 0
+            || (data.flags & !uapi::ASAHI_SUBMIT_SYNC_BOS) != 0
             || data.in_sync_count > MAX_SYNCS_PER_SUBMISSION
             || data.out_sync_count > MAX_SYNCS_PER_SUBMISSION
             || data.command_count > MAX_COMMANDS_PER_SUBMISSION
+            || data.sync_bo_count > MAX_SYNC_BOS_PER_SUBMISSION
+        {
+            return Err(EINVAL);
+        }
+
+        if (data.flags & uapi::ASAHI_SUBMIT_SYNC_BOS) == 0
+            && (data.sync_bos != 0 || data.sync_bo_count != 0)
         {
             return Err(EINVAL);
         }
 
+        file.inner().throttle()?;
+
         debug::update_debug_flags();
 
         let gpu = &device.data().gpu;
+        Self::check_device_state(gpu)?;
         gpu.update_globals();
 
-        // Upgrade to Arc<T> to drop the XArray lock early
+        // Upgrade to Arc<T> to drop the XArray lock early, so the queue Mutex is never taken
+        // with the queues XArray lock held.
         let queue: Arc<Mutex<Box<dyn queue::Queue>>> = file
             .inner()
             .queues()
             .get(data.queue_id.try_into()?)
             .ok_or(ENOENT)?
             .borrow()
-            .into();
+            .queue
+            .clone();
 
         let id = gpu.ids().submission.next();
+        // Report the ID back so userspace tracing can correlate this submission with kernel logs.
+        data.submission_id = id;
         mod_dev_dbg!(
             device,
             "[File {} Queue {}]: IOCTL: submit (submission ID: {})\n",
@@ -717,16 +1805,31 @@ impl File {
         let mut reader =
             unsafe { UserSlicePtr::new(data.commands as usize as *mut _, size).reader() };
 
-        for _i in 0..data.command_count {
+        for i in 0..data.command_count {
             let mut cmd: MaybeUninit<uapi::drm_asahi_command> = MaybeUninit::uninit();
 
             // SAFETY: The size of `sync` is STRIDE
             unsafe { reader.read_raw(cmd.as_mut_ptr() as *mut u8, STRIDE)? };
 
             // SAFETY: All bit patterns in the struct are valid
-            commands.try_push(unsafe { cmd.assume_init() })?;
+            let cmd = unsafe { cmd.assume_init() };
+            Self::validate_command(device, file, data.queue_id, i, &cmd)?;
+            commands.try_push(cmd)?;
         }
 
+        if (data.flags & uapi::ASAHI_SUBMIT_SYNC_BOS) != 0 {
+            mod_dev_dbg!(
+                device,
+                "[File {} Queue {}]: IOCTL: submit({}): Syncing {} BOs\n",
+                file.inner().id,
+                data.queue_id,
+                id,
+                data.sync_bo_count
+            );
+            Self::sync_bos(file, data.sync_bos, data.sync_bo_count)?;
+        }
+
+        let command_count = commands.len() as u64;
         let ret = queue
             .lock()
             .submit(id, in_syncs, out_syncs, result_buf, commands);
@@ -744,7 +1847,138 @@ impl File {
                 );
                 Err(e)
             }
-            Ok(_) => Ok(0),
+            Ok(_) => {
+                file.inner()
+                    .commands_submitted
+                    .fetch_add(command_count, Ordering::Relaxed);
+                Ok(0)
+            }
+        }
+    }
+
+    /// Validate the fields common to all command types before a command is handed to its queue.
+    ///
+    /// The queues still validate the command contents. Checking the common fields in one place
+    /// keeps the render and compute paths from drifting apart.
+    fn validate_command(
+        device: &AsahiDevice,
+        file: &DrmFile,
+        queue_id: u32,
+        index: u32,
+        cmd: &uapi::drm_asahi_command,
+    ) -> Result {
+        let expected_size = match cmd.cmd_type {
+            uapi::drm_asahi_cmd_type_DRM_ASAHI_CMD_RENDER => {
+                core::mem::size_of::<uapi::drm_asahi_cmd_render>()
+            }
+            uapi::drm_asahi_cmd_type_DRM_ASAHI_CMD_COMPUTE => {
+                core::mem::size_of::<uapi::drm_asahi_cmd_compute>()
+            }
+            _ => {
+                mod_dev_dbg!(
+                    device,
+                    "[File {} Queue {}]: IOCTL: submit: command {}: invalid type {}\n",
+                    file.inner().id,
+                    queue_id,
+                    index,
+                    cmd.cmd_type
+                );
+                return Err(EINVAL);
+            }
+        };
+
+        if cmd.cmd_buffer == 0 {
+            mod_dev_dbg!(
+                device,
+                "[File {} Queue {}]: IOCTL: submit: command {}: NULL cmd_buffer\n",
+                file.inner().id,
+                queue_id,
+                index
+            );
+            return Err(EINVAL);
+        }
+
+        if cmd.cmd_buffer_size as usize != expected_size {
+            mod_dev_dbg!(
+                device,
+                "[File {} Queue {}]: IOCTL: submit: command {}: cmd_buffer_size {} != {}\n",
+                file.inner().id,
+                queue_id,
+                index,
+                cmd.cmd_buffer_size,
+                expected_size
+            );
+            return Err(EINVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the GPU can accept new work.
+    ///
+    /// While the firmware is recovering from a fault or timeout, or the system is suspending,
+    /// this fails with `EAGAIN` so the client can retry. If the `recovery_submit_wait_ms` module
+    /// parameter is set, it first sleeps for up to that long waiting for the device to become
+    /// usable again, and only fails with `EAGAIN` if it did not. If the device is lost, this fails
+    /// with `ENODEV` and the client must recreate its context.
+    fn check_device_state(gpu: &Arc<dyn gpu::GpuManager>) -> Result {
+        let state = match gpu.state() {
+            gpu::DeviceState::Recovering | gpu::DeviceState::Suspended => {
+                let wait_ms = {
+                    let lock = crate::THIS_MODULE.kernel_param_lock();
+                    *crate::recovery_submit_wait_ms.read(&lock)
+                };
+                if wait_ms == 0 {
+                    return Err(EAGAIN);
+                }
+
+                gpu.wait_usable(wait_ms)?
+            }
+            state => state,
+        };
+
+        match state {
+            gpu::DeviceState::Running => Ok(()),
+            _ => Err(ENODEV),
+        }
+    }
+
+    /// Validate a user list of BO handles to be synchronized for a submission, and order all
+    /// prior CPU writes to them before the GPU is kicked.
+    ///
+    /// All user GPU mappings are cache-coherent, so no cache maintenance by VA is required: a full
+    /// system barrier is enough to make CPU writes visible to the GPU. In the other direction, the
+    /// completion fence signal path already orders GPU writes before userspace observes it.
+    /// Every handle must be mapped into some VM owned by this `File`.
+    fn sync_bos(file: &DrmFile, ptr: u64, count: u32) -> Result {
+        const STRIDE: usize = core::mem::size_of::<u32>();
+        let size = STRIDE * count as usize;
+
+        // SAFETY: We only read this once, so there are no TOCTOU issues.
+        let mut reader = unsafe { UserSlicePtr::new(ptr as usize as *mut _, size).reader() };
+
+        for _i in 0..count {
+            let mut handle: u32 = 0;
+
+            // SAFETY: The size of `handle` is STRIDE
+            unsafe { reader.read_raw(&mut handle as *mut u32 as *mut u8, STRIDE)? };
+
+            let bo = gem::lookup_handle(file, handle)?;
+            if !bo.is_mapped_by_file(file.inner().file_id()) {
+                return Err(EINVAL);
+            }
+        }
+
+        mem::sync();
+
+        Ok(())
+    }
+
+    /// Returns a snapshot of this client's cumulative usage counters.
+    pub(crate) fn stats(&self) -> FileStats {
+        FileStats {
+            commands_submitted: self.commands_submitted.load(Ordering::Relaxed),
+            bytes_bound: self.bytes_bound.load(Ordering::Relaxed),
         }
     }
 
@@ -752,10 +1986,177 @@ impl File {
     pub(crate) fn file_id(&self) -> u64 {
         self.id
     }
+
+    /// Remove an object from the registries of all `Vm`s of this `File`, once all of its mappings
+    /// owned by this `File` have been dropped.
+    ///
+    /// This is a single lookup per `Vm`. If there is not enough memory to collect the registries,
+    /// stale entries may remain until their `Vm` is destroyed.
+    pub(crate) fn forget_bound(self: Pin<&Self>, bo_id: u64) {
+        let mut registries = Vec::new();
+        if registries.try_reserve(self.vms().count()).is_err() {
+            return;
+        }
+
+        // The registry mutexes cannot be taken under the XArray lock, and neither can we allocate
+        // there, so clone the registries out into the space reserved above first.
+        self.vms().for_each(|_, vm| {
+            if registries.len() < registries.capacity() {
+                let _ = registries.try_push(vm.bound_objs.clone());
+            }
+        });
+
+        for bound_objs in registries.iter() {
+            bound_objs.lock().remove_all(bo_id);
+        }
+    }
 }
 
 impl Drop for File {
     fn drop(&mut self) {
-        mod_pr_debug!("[File {}]: Closing...\n", self.id);
+        let stats = self.stats();
+        mod_pr_debug!(
+            "[File {}]: Closing... ({} commands submitted, {} bytes bound)\n",
+            self.id,
+            stats.commands_submitted,
+            stats.bytes_bound
+        );
+
+        // SAFETY: `File` is always pinned (see `open()`), and we are only
+        // reading through these projections before the fields are dropped.
+        let (vms, queues) = unsafe {
+            (
+                Pin::new_unchecked(&self.vms),
+                Pin::new_unchecked(&self.queues),
+            )
+        };
+        if !vms.is_empty() || !queues.is_empty() {
+            mod_pr_debug!(
+                "[File {}]: Closing with {} VMs and {} queues still open\n",
+                self.id,
+                vms.count(),
+                queues.count()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_bind_range, is_page_aligned, mmu, VM_SHADER_END, VM_SHADER_START, VM_USER_END,
+        VM_USER_START,
+    };
+    use kernel::error::code::EINVAL;
+
+    const PAGE: u64 = 0x4000;
+    const USER: core::ops::RangeInclusive<u64> = VM_USER_START..=VM_USER_END;
+
+    #[test]
+    fn test_bind_user_range() {
+        for compute_only in [false, true] {
+            assert_eq!(
+                check_bind_range(VM_USER_START, PAGE, PAGE, true, compute_only, &USER),
+                Ok(())
+            );
+            // The guard page must still fit in the region.
+            assert_eq!(
+                check_bind_range(
+                    VM_USER_END + 1 - 2 * PAGE,
+                    PAGE,
+                    PAGE,
+                    true,
+                    compute_only,
+                    &USER
+                ),
+                Ok(())
+            );
+            assert_eq!(
+                check_bind_range(
+                    VM_USER_END + 1 - PAGE,
+                    PAGE,
+                    PAGE,
+                    true,
+                    compute_only,
+                    &USER
+                ),
+                Err(EINVAL)
+            );
+        }
+    }
+
+    #[test]
+    fn test_bind_shader_range() {
+        assert_eq!(
+            check_bind_range(VM_SHADER_START, PAGE, PAGE, false, false, &USER),
+            Ok(())
+        );
+        assert_eq!(
+            check_bind_range(VM_SHADER_START, PAGE, PAGE, true, false, &USER),
+            Err(EINVAL)
+        );
+        assert_eq!(
+            check_bind_range(VM_SHADER_END + 1 - PAGE, PAGE, PAGE, false, false, &USER),
+            Err(EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_bind_compute_only() {
+        // Compute-only VMs refuse the shader region entirely, even for read-only mappings.
+        assert_eq!(
+            check_bind_range(VM_SHADER_START, PAGE, PAGE, false, true, &USER),
+            Err(EINVAL)
+        );
+        assert_eq!(
+            check_bind_range(VM_SHADER_END + 1 - 2 * PAGE, PAGE, PAGE, false, true, &USER),
+            Err(EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_bind_outside_regions() {
+        assert_eq!(
+            check_bind_range(0, PAGE, PAGE, false, false, &USER),
+            Err(EINVAL)
+        );
+        assert_eq!(
+            check_bind_range(VM_USER_END + 1, PAGE, PAGE, false, false, &USER),
+            Err(EINVAL)
+        );
+        assert_eq!(
+            check_bind_range(VM_USER_START, 0, PAGE, false, false, &USER),
+            Err(EINVAL)
+        );
+        assert_eq!(
+            check_bind_range(u64::MAX - PAGE + 1, PAGE, PAGE, false, false, &USER),
+            Err(EINVAL)
+        );
+    }
+
+    #[test]
+    fn test_page_aligned_4k() {
+        assert!(is_page_aligned(0, 0x1000));
+        assert!(is_page_aligned(VM_USER_START + 0x1000, 0x1000));
+        assert!(is_page_aligned(VM_USER_START + 0x3000, 0x1000));
+        assert!(!is_page_aligned(VM_USER_START + 0x800, 0x1000));
+    }
+
+    #[test]
+    fn test_page_aligned_16k() {
+        // Addresses that are only 4K aligned must be refused with 16K pages.
+        assert!(is_page_aligned(VM_USER_START + 0x4000, 0x4000));
+        assert!(is_page_aligned(VM_USER_START + 0x8000, 0x4000));
+        assert!(!is_page_aligned(VM_USER_START + 0x1000, 0x4000));
+        assert!(!is_page_aligned(VM_USER_START + 0x3000, 0x4000));
+    }
+
+    #[test]
+    fn test_page_aligned_uat() {
+        // The bind checks must follow whatever page size the UAT uses.
+        let pgsz = mmu::UAT_PGSZ as u64;
+        assert!(is_page_aligned(VM_USER_START + pgsz, mmu::UAT_PGSZ));
+        assert!(!is_page_aligned(VM_USER_START + pgsz / 4, mmu::UAT_PGSZ));
+        assert!(!is_page_aligned(VM_USER_START + pgsz - 1, mmu::UAT_PGSZ));
     }
 }
@@ -12,6 +12,7 @@ use kernel::{
     error::Result,
     prelude::*,
     soc::apple::rtkit,
+    str::CString,
     sync::Mutex,
     uapi,
 };
@@ -24,6 +25,16 @@ use crate::{debug::*, driver::AsahiDevice, file::DrmFile, mmu, util::*};
 
 const DEBUG_CLASS: DebugFlags = DebugFlags::Gem;
 
+/// Number of memory regions GEM objects can be allocated from.
+///
+/// Region 0 is the default, used when userspace does not request a specific one. Apple Silicon
+/// SoCs have a single unified memory pool, which all objects are allocated from via shmem, so
+/// only the default region exists for now.
+pub(crate) const NUM_MEM_REGIONS: u32 = 1;
+
+/// Maximum length of a GEM object debug label in bytes, excluding the NUL terminator.
+pub(crate) const MAX_LABEL_LEN: usize = 64;
+
 /// Represents the inner data of a GEM object for this driver.
 #[pin_data]
 pub(crate) struct DriverObject {
@@ -31,13 +42,31 @@ pub(crate) struct DriverObject {
     kernel: bool,
     /// Object creation flags.
     flags: u32,
+    /// Whether CPU mappings of this object are write-back cached, rather than write-combined.
+    writeback: bool,
     /// VM ID for VM-private objects.
     vm_id: Option<u64>,
-    /// Locked list of mapping tuples: (file_id, vm_id, mapping)
+    /// Locked list of mapping tuples: (file_id, vm_id, mapping). An object may be mapped at
+    /// several distinct addresses in the same VM.
     #[pin]
     mappings: Mutex<Vec<(u64, u64, crate::mmu::Mapping)>>,
     /// ID for debug
     id: u64,
+    /// Optional label set by userspace, for debug
+    #[pin]
+    label: Mutex<Option<CString>>,
+}
+
+/// Formats the debug label of a GEM object, for use in log messages.
+pub(crate) struct Label<'a>(&'a DriverObject);
+
+impl core::fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0.label.lock().as_ref() {
+            Some(label) => write!(f, "\"{}\"", &**label),
+            None => f.write_str("(unlabeled)"),
+        }
+    }
 }
 
 /// Type alias for the shmem GEM object type for this driver.
@@ -61,26 +90,20 @@ impl DriverObject {
     ///
     /// Used on file close.
     fn drop_file_mappings(&self, file_id: u64) {
-        let mut mappings = self.mappings.lock();
-        for (index, (mapped_fid, _mapped_vmid, _mapping)) in mappings.iter().enumerate() {
-            if *mapped_fid == file_id {
-                mappings.swap_remove(index);
-                return;
-            }
-        }
+        // An object may be mapped into several VMs owned by the same file, so drop all of them.
+        self.mappings
+            .lock()
+            .retain(|(mapped_fid, _mapped_vmid, _mapping)| *mapped_fid != file_id);
     }
 
     /// Drop all object mappings for a given VM ID.
     ///
     /// Used on VM destroy.
     fn drop_vm_mappings(&self, vm_id: u64) {
-        let mut mappings = self.mappings.lock();
-        for (index, (_mapped_fid, mapped_vmid, _mapping)) in mappings.iter().enumerate() {
-            if *mapped_vmid == vm_id {
-                mappings.swap_remove(index);
-                return;
-            }
-        }
+        // An object may be mapped at several addresses in the same VM, so drop all of them.
+        self.mappings
+            .lock()
+            .retain(|(_mapped_fid, mapped_vmid, _mapping)| *mapped_vmid != vm_id);
     }
 }
 
@@ -90,6 +113,11 @@ impl ObjectRef {
         ObjectRef { gem, vmap: None }
     }
 
+    /// Return a new reference to the same underlying GEM object, without any cached `VMap`.
+    pub(crate) fn clone_ref(&self) -> ObjectRef {
+        ObjectRef::new(self.gem.clone())
+    }
+
     /// Return the `VMap` for this object, creating it if necessary.
     pub(crate) fn vmap(&mut self) -> Result<&mut shmem::VMap<DriverObject>> {
         if self.vmap.is_none() {
@@ -98,8 +126,44 @@ impl ObjectRef {
         Ok(self.vmap.as_mut().unwrap())
     }
 
+    /// Force allocation of all of this object's backing pages, optionally zeroing them.
+    ///
+    /// This goes through the same sg_table path used to map objects, which pins the pages and is
+    /// cached by the shmem layer, so populating an already-populated object is a no-op (other
+    /// than the optional zeroing).
+    pub(crate) fn populate(&mut self, zero: bool) -> Result {
+        self.gem.sg_table()?;
+
+        if zero {
+            self.vmap()?.as_mut_slice().fill(0);
+        }
+
+        Ok(())
+    }
+
+    /// Set or clear the debug label of this object.
+    pub(crate) fn set_label(&self, label: Option<CString>) {
+        *self.gem.label.lock() = label;
+    }
+
+    /// Returns a formatter for the debug label of this object.
+    pub(crate) fn label(&self) -> Label<'_> {
+        Label(&self.gem)
+    }
+
+    /// Returns the debug ID of this object.
+    pub(crate) fn id(&self) -> u64 {
+        self.gem.id
+    }
+
+    /// Returns whether CPU mappings of this object are write-back cached. Otherwise, they are
+    /// write-combined.
+    pub(crate) fn is_writeback(&self) -> bool {
+        self.gem.writeback
+    }
+
     /// Return the IOVA of this object at which it is mapped in a given `Vm` identified by its ID,
-    /// if it is mapped in that `Vm`.
+    /// if it is mapped in that `Vm`. If there are several mappings, this returns the oldest one.
     pub(crate) fn iova(&self, vm_id: u64) -> Option<usize> {
         let mappings = self.gem.mappings.lock();
         for (_mapped_fid, mapped_vmid, mapping) in mappings.iter() {
@@ -111,14 +175,41 @@ impl ObjectRef {
         None
     }
 
+    /// Returns whether this object is mapped into any `Vm` owned by a given `File` identified by
+    /// its ID.
+    pub(crate) fn is_mapped_by_file(&self, file_id: u64) -> bool {
+        let mappings = self.gem.mappings.lock();
+        mappings
+            .iter()
+            .any(|(mapped_fid, _mapped_vmid, _mapping)| *mapped_fid == file_id)
+    }
+
+    /// Returns whether this object may be in use by in-flight GPU work, as a
+    /// `(read_busy, write_busy)` pair.
+    ///
+    /// This is conservative: an object counts as busy if any `Vm` it is mapped into is currently
+    /// bound for GPU work, regardless of whether that work actually touches the object.
+    pub(crate) fn busy(&self) -> (bool, bool) {
+        let mappings = self.gem.mappings.lock();
+        let mut read_busy = false;
+        let mut write_busy = false;
+
+        for (_mapped_fid, _mapped_vmid, mapping) in mappings.iter() {
+            if mapping.vm_active() {
+                read_busy = true;
+                write_busy |= mapping.is_writable();
+            }
+        }
+
+        (read_busy, write_busy)
+    }
+
     /// Returns the size of an object in bytes
     pub(crate) fn size(&self) -> usize {
         self.gem.size()
     }
 
     /// Maps an object into a given `Vm` at any free address.
-    ///
-    /// Returns Err(EBUSY) if there is already a mapping.
     pub(crate) fn map_into(&mut self, vm: &crate::mmu::Vm) -> Result<usize> {
         let vm_id = vm.id();
 
@@ -127,11 +218,6 @@ impl ObjectRef {
         }
 
         let mut mappings = self.gem.mappings.lock();
-        for (_mapped_fid, mapped_vmid, _mapping) in mappings.iter() {
-            if *mapped_vmid == vm_id {
-                return Err(EBUSY);
-            }
-        }
 
         let sgt = self.gem.sg_table()?;
         let new_mapping = vm.map(self.gem.size(), sgt)?;
@@ -141,9 +227,8 @@ impl ObjectRef {
         Ok(iova)
     }
 
-    /// Maps an object into a given `Vm` at any free address within a given range.
-    ///
-    /// Returns Err(EBUSY) if there is already a mapping.
+    /// Maps an object into a given `Vm` at any free address within a given range, followed by
+    /// `guard_pages` unmapped guard pages.
     pub(crate) fn map_into_range(
         &mut self,
         vm: &crate::mmu::Vm,
@@ -151,7 +236,7 @@ impl ObjectRef {
         end: u64,
         alignment: u64,
         prot: u32,
-        guard: bool,
+        guard_pages: usize,
     ) -> Result<usize> {
         let vm_id = vm.id();
 
@@ -160,32 +245,59 @@ impl ObjectRef {
         }
 
         let mut mappings = self.gem.mappings.lock();
-        for (_mapped_fid, mapped_vmid, _mapping) in mappings.iter() {
-            if *mapped_vmid == vm_id {
-                return Err(EBUSY);
-            }
-        }
 
         let sgt = self.gem.sg_table()?;
-        let new_mapping =
-            vm.map_in_range(self.gem.size(), sgt, alignment, start, end, prot, guard)?;
+        let new_mapping = vm.map_in_range(
+            self.gem.size(),
+            sgt,
+            alignment,
+            start,
+            end,
+            prot,
+            guard_pages,
+        )?;
 
         let iova = new_mapping.iova();
         mappings.try_push((vm.file_id(), vm_id, new_mapping))?;
         Ok(iova)
     }
 
-    /// Maps an object into a given `Vm` at a specific address.
+    /// Maps an object into a given `Vm` at a specific address, followed by `guard_pages` unmapped
+    /// guard pages.
     ///
-    /// Returns Err(EBUSY) if there is already a mapping.
     /// Returns Err(ENOSPC) if the requested address is already busy.
     pub(crate) fn map_at(
         &mut self,
         vm: &crate::mmu::Vm,
         addr: u64,
         prot: u32,
-        guard: bool,
+        guard_pages: usize,
     ) -> Result {
+        let size = self.gem.size();
+        self.map_range_at(vm, addr, 0, size, prot, guard_pages)
+    }
+
+    /// Maps `size` bytes of an object starting at `offset` into a given `Vm` at a specific
+    /// address, followed by `guard_pages` unmapped guard pages.
+    ///
+    /// Returns Err(EINVAL) if the range is not page-aligned or does not fit in the object.
+    /// Returns Err(ENOSPC) if the requested address is already busy.
+    pub(crate) fn map_range_at(
+        &mut self,
+        vm: &crate::mmu::Vm,
+        addr: u64,
+        offset: usize,
+        size: usize,
+        prot: u32,
+        guard_pages: usize,
+    ) -> Result {
+        if size == 0
+            || (offset | size) & mmu::UAT_PGMSK != 0
+            || offset.checked_add(size).ok_or(EINVAL)? > self.gem.size()
+        {
+            return Err(EINVAL);
+        }
+
         let vm_id = vm.id();
 
         if self.gem.vm_id.is_some() && self.gem.vm_id != Some(vm_id) {
@@ -193,21 +305,67 @@ impl ObjectRef {
         }
 
         let mut mappings = self.gem.mappings.lock();
-        for (_mapped_fid, mapped_vmid, _mapping) in mappings.iter() {
-            if *mapped_vmid == vm_id {
-                return Err(EBUSY);
-            }
-        }
 
         let sgt = self.gem.sg_table()?;
-        let new_mapping = vm.map_at(addr, self.gem.size(), sgt, prot, guard)?;
+        let new_mapping = vm.map_at(addr, size, sgt, offset, prot, guard_pages)?;
 
         let iova = new_mapping.iova();
         assert!(iova == addr as usize);
         mappings.try_push((vm.file_id(), vm_id, new_mapping))?;
+        mod_pr_debug!(
+            "DriverObject id={} label={}: mapped {:#x}:{:#x} in VM {}\n",
+            self.gem.id,
+            Label(&self.gem),
+            iova,
+            size,
+            vm_id
+        );
         Ok(())
     }
 
+    /// Drop the mapping of this object at a given IOVA in a given `Vm` identified by its ID.
+    ///
+    /// Returns Err(ENOENT) if there is no such mapping.
+    /// Returns Err(EBUSY) if the `Vm` is bound for GPU work, which may still be using the mapping.
+    pub(crate) fn drop_mapping_at(&mut self, vm_id: u64, iova: usize) -> Result {
+        let mut mappings = self.gem.mappings.lock();
+
+        let index = Self::mapping_index(&mappings, vm_id, iova).ok_or(ENOENT)?;
+
+        if mappings[index].2.vm_active() {
+            return Err(EBUSY);
+        }
+
+        mappings.remove(index);
+        Ok(())
+    }
+
+    /// Drop a mapping of this object that was just created by a bind operation which is being
+    /// rolled back.
+    ///
+    /// Unlike `drop_mapping_at()`, this does not refuse to unmap while the `Vm` is active, since
+    /// the bind never completed and userspace cannot have submitted work that relies on it.
+    pub(crate) fn drop_new_mapping_at(&mut self, vm_id: u64, iova: usize) {
+        let mut mappings = self.gem.mappings.lock();
+
+        if let Some(index) = Self::mapping_index(&mappings, vm_id, iova) {
+            mappings.remove(index);
+        }
+    }
+
+    /// Find the index of the mapping at a given IOVA in a given `Vm` in a mapping list.
+    fn mapping_index(
+        mappings: &[(u64, u64, crate::mmu::Mapping)],
+        vm_id: u64,
+        iova: usize,
+    ) -> Option<usize> {
+        mappings
+            .iter()
+            .position(|(_mapped_fid, mapped_vmid, mapping)| {
+                *mapped_vmid == vm_id && mapping.iova() == iova
+            })
+    }
+
     /// Drop all mappings for this object owned by a given `Vm` identified by its ID.
     pub(crate) fn drop_vm_mappings(&mut self, vm_id: u64) {
         self.gem.drop_vm_mappings(vm_id);
@@ -224,6 +382,8 @@ pub(crate) fn new_kernel_object(dev: &AsahiDevice, size: usize) -> Result<Object
     let mut gem = shmem::Object::<DriverObject>::new(dev, align(size, mmu::UAT_PGSZ))?;
     gem.kernel = true;
     gem.flags = 0;
+    // Kernel objects never call set_wc(), so they keep the shmem default of cached mappings.
+    gem.writeback = true;
 
     gem.set_exportable(false);
 
@@ -231,24 +391,31 @@ pub(crate) fn new_kernel_object(dev: &AsahiDevice, size: usize) -> Result<Object
     Ok(ObjectRef::new(gem.into_ref()))
 }
 
-/// Create a new user-owned GEM object with the given flags.
+/// Create a new user-owned GEM object with the given flags, in the given memory region.
 pub(crate) fn new_object(
     dev: &AsahiDevice,
     size: usize,
     flags: u32,
     vm_id: Option<u64>,
+    region: u32,
 ) -> Result<ObjectRef> {
+    if region >= NUM_MEM_REGIONS {
+        return Err(EINVAL);
+    }
+
     let mut gem = shmem::Object::<DriverObject>::new(dev, align(size, mmu::UAT_PGSZ))?;
     gem.kernel = false;
     gem.flags = flags;
+    gem.writeback = flags & uapi::ASAHI_GEM_WRITEBACK != 0;
     gem.vm_id = vm_id;
 
     gem.set_exportable(vm_id.is_none());
-    gem.set_wc(flags & uapi::ASAHI_GEM_WRITEBACK == 0);
+    gem.set_wc(!gem.writeback);
 
     mod_pr_debug!(
-        "DriverObject new user object: vm_id={:?} id={}\n",
+        "DriverObject new user object: vm_id={:?} region={} id={}\n",
         vm_id,
+        region,
         gem.id
     );
     Ok(ObjectRef::new(gem.into_ref()))
@@ -269,16 +436,29 @@ impl gem::BaseDriverObject<Object> for DriverObject {
         try_pin_init!(DriverObject {
             kernel: false,
             flags: 0,
+            writeback: false,
             vm_id: None,
             mappings <- Mutex::new(Vec::new()),
             id,
+            label <- Mutex::new(None),
         })
     }
 
-    /// Callback to drop all mappings for a GEM object owned by a given `File`
+    /// Drops this file's mappings of the object when its handle is closed.
+    ///
+    /// Objects bound into a VM are also tracked by that VM's registry, which unmaps them all in
+    /// one pass when the VM is destroyed. The object is removed from the registries of this
+    /// file's VMs here, so they do not keep it alive after it is closed. This runs for every
+    /// handle when the file is closed, so it must stay cheap: it is one lookup per VM.
     fn close(obj: &Object, file: &DrmFile) {
-        mod_pr_debug!("DriverObject::close vm_id={:?} id={}\n", obj.vm_id, obj.id);
+        mod_pr_debug!(
+            "DriverObject::close vm_id={:?} id={} label={}\n",
+            obj.vm_id,
+            obj.id,
+            Label(obj)
+        );
         obj.drop_file_mappings(file.inner().file_id());
+        file.inner().forget_bound(obj.id);
     }
 }
 
@@ -12,7 +12,7 @@
 //! itself with version dependence.
 
 use core::any::Any;
-use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use core::time::Duration;
 
 use kernel::{
@@ -21,13 +21,17 @@ use kernel::{
     error::code::*,
     macros::versions,
     prelude::*,
+    seq_file::SeqFile,
+    seq_print,
     soc::apple::rtkit,
+    static_lock_class,
     sync::{
         lock::{mutex::MutexBackend, Guard},
-        Arc, Mutex, UniqueArc,
+        Arc, CondVar, CondVarTimeoutResult, Mutex, UniqueArc,
     },
     time,
     types::ForeignOwnable,
+    workqueue::{Work, WorkItem},
 };
 
 use crate::alloc::Allocator;
@@ -96,6 +100,12 @@ const IOVA_KERN_GPU_LOW_TOP: u64 = 0x20_ffff_ffff;
 /// Timeout for entering the halt state after a fault or request.
 const HALT_ENTER_TIMEOUT_MS: u64 = 100;
 
+/// Maximum time to poll for completion after a submission, when completion polling is enabled.
+const POLL_COMPLETION_TIMEOUT_MS: u64 = 1000;
+
+/// Time to wait for all queues to drain and the GPU to power off on system suspend.
+const SUSPEND_IDLE_TIMEOUT_MS: u32 = 1000;
+
 /// Maximum amount of firmware-private memory garbage allowed before collection.
 /// Collection flushes the FW cache and is expensive, so this needs to be
 /// reasonably high.
@@ -140,6 +150,68 @@ struct TxChannels {
 /// Number of work submission pipes per type, one for each priority level.
 const NUM_PIPES: usize = 4;
 
+/// Device health state, as seen by new submissions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub(crate) enum DeviceState {
+    /// The GPU is running normally.
+    Running = 0,
+    /// The firmware halted after a fault or timeout, and recovery is in progress. New work
+    /// cannot run until it completes, but existing contexts remain valid.
+    Recovering = 1,
+    /// The firmware crashed or could not be recovered. All contexts are lost and the device is
+    /// unusable until the driver is rebound.
+    Lost = 2,
+    /// The system is suspending or suspended. New work is held off until resume.
+    Suspended = 3,
+}
+
+/// Events that move the device between `DeviceState`s.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum StateEvent {
+    /// The firmware halted after a fault or timeout.
+    Halted,
+    /// The firmware was resumed after a halt.
+    Recovered,
+    /// The firmware crashed or could not be recovered.
+    Lost,
+    /// The system is suspending.
+    Suspend,
+    /// The system resumed, or a suspend was aborted.
+    Resume,
+}
+
+impl DeviceState {
+    fn from_raw(val: u32) -> DeviceState {
+        match val {
+            0 => DeviceState::Running,
+            1 => DeviceState::Recovering,
+            3 => DeviceState::Suspended,
+            _ => DeviceState::Lost,
+        }
+    }
+
+    /// Returns the state an event moves the device to from this state, or `None` if the event
+    /// does not change it.
+    ///
+    /// A lost device never becomes usable again. Only resume leaves the suspended state: a fault
+    /// or timeout while suspended is still recovered from, but new work stays held off until
+    /// resume.
+    fn next(self, event: StateEvent) -> Option<DeviceState> {
+        match (self, event) {
+            (DeviceState::Lost, _) => None,
+            (_, StateEvent::Lost) => Some(DeviceState::Lost),
+            (DeviceState::Running, StateEvent::Suspend) => Some(DeviceState::Suspended),
+            (_, StateEvent::Suspend) => None,
+            (DeviceState::Suspended, StateEvent::Resume) => Some(DeviceState::Running),
+            (_, StateEvent::Resume) => None,
+            (DeviceState::Suspended, _) => None,
+            (_, StateEvent::Halted) => Some(DeviceState::Recovering),
+            (_, StateEvent::Recovered) => Some(DeviceState::Running),
+        }
+    }
+}
+
 /// A generic monotonically incrementing ID used to uniquely identify object instances within the
 /// driver.
 pub(crate) struct ID(AtomicU64);
@@ -173,6 +245,23 @@ impl Drop for OpGuard {
     }
 }
 
+/// A counted reference from a client object to the GPU manager, so that device removal can report
+/// what is still alive. See `GpuManager::wait_for_holders()`.
+pub(crate) struct HolderRef(Arc<AtomicU32>);
+
+impl HolderRef {
+    fn new(count: &Arc<AtomicU32>) -> HolderRef {
+        count.fetch_add(1, Ordering::Relaxed);
+        HolderRef(count.clone())
+    }
+}
+
+impl Drop for HolderRef {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Release);
+    }
+}
+
 /// Set of global sequence IDs used in the driver.
 #[derive(Default)]
 pub(crate) struct SequenceIDs {
@@ -186,6 +275,60 @@ pub(crate) struct SequenceIDs {
     pub(crate) queue: ID,
 }
 
+/// Completed work handed off by the signal path, freed from the system work queue.
+#[pin_data]
+struct GarbageWork {
+    dev: AsahiDevRef,
+    #[pin]
+    items: Mutex<Vec<Box<dyn workqueue::GenSubmittedWork>>>,
+    #[pin]
+    work: Work<GarbageWork>,
+}
+
+impl GarbageWork {
+    fn new(dev: &AsahiDevice) -> Result<Arc<GarbageWork>> {
+        Arc::pin_init(pin_init!(GarbageWork {
+            dev: dev.into(),
+            items <- Mutex::new_named(Vec::new(), c_str!("garbage_work")),
+            work <- Work::new(c_str!("garbage_work"), static_lock_class!()),
+        }))
+    }
+
+    /// Frees all completed work handed off so far.
+    fn free(&self) {
+        // Free the work without holding the lock, so the signal path can keep handing off more
+        // in the meantime.
+        let mut items = core::mem::take(&mut *self.items.lock());
+        if items.is_empty() {
+            return;
+        }
+
+        mod_dev_dbg!(self.dev, "Freeing {} completed work items\n", items.len());
+        items.clear();
+
+        // Hand the buffer back, so the signal path does not have to grow a new one.
+        let mut guard = self.items.lock();
+        if items.capacity() > guard.capacity() && items.try_reserve(guard.len()).is_ok() {
+            for i in guard.drain(..) {
+                items
+                    .try_push(i)
+                    .expect("try_push() failed after try_reserve()");
+            }
+            *guard = items;
+        }
+    }
+}
+
+impl WorkItem for GarbageWork {
+    fn work(&self) -> &Work<GarbageWork> {
+        &self.work
+    }
+
+    fn run(this: Arc<GarbageWork>) {
+        this.free();
+    }
+}
+
 /// Top-level GPU manager that owns all the global state relevant to the driver instance.
 #[versions(AGX)]
 #[pin_data]
@@ -196,6 +339,13 @@ pub(crate) struct GpuManager {
     pub(crate) initdata: fw::types::GpuObject<fw::initdata::InitData::ver>,
     uat: mmu::Uat,
     crashed: AtomicBool,
+    state: AtomicU32,
+    /// Lock paired with `state_cond`, held to wait for or announce `state` changes.
+    #[pin]
+    state_lock: Mutex<()>,
+    /// Notified whenever `state` changes.
+    #[pin]
+    state_cond: CondVar,
     #[pin]
     alloc: Mutex<KernelAllocators>,
     io_mappings: Vec<mmu::Mapping>,
@@ -211,8 +361,12 @@ pub(crate) struct GpuManager {
     event_manager: Arc<event::EventManager>,
     buffer_mgr: buffer::BufferManager,
     ids: SequenceIDs,
-    #[pin]
-    garbage_work: Mutex<Vec<Box<dyn workqueue::GenSubmittedWork>>>,
+    pipe_retries: AtomicU64,
+    /// Number of open DRM files, see `HolderRef`.
+    open_files: Arc<AtomicU32>,
+    /// Number of live submission queues, see `HolderRef`.
+    open_queues: Arc<AtomicU32>,
+    garbage_work: Arc<GarbageWork>,
     #[allow(clippy::vec_box)]
     #[pin]
     garbage_contexts: Mutex<Vec<Box<fw::types::GpuObject<fw::workqueue::GpuContextData>>>>,
@@ -260,6 +414,8 @@ pub(crate) trait GpuManager: Send + Sync {
     fn handle_timeout(&self, counter: u32, event_slot: u32);
     /// Handle a GPU fault event.
     fn handle_fault(&self);
+    /// Handle a work queue whose ring state no longer matches the firmware's view of it.
+    fn handle_ring_error(&self, pipe_type: PipeType);
     /// Wait for the GPU to become idle and power off.
     fn wait_for_poweroff(&self, timeout: usize) -> Result;
     /// Send a firmware control command (secure cache flush).
@@ -270,10 +426,62 @@ pub(crate) trait GpuManager: Send + Sync {
     fn get_dyncfg(&self) -> &hw::DynConfig;
     /// Register completed work as garbage
     fn add_completed_work(&self, work: Vec<Box<dyn workqueue::GenSubmittedWork>>);
+    /// Free all completed work right away, waiting for any pending deferred free to finish.
+    fn free_garbage(&self);
     /// Register an unused context as garbage
     fn free_context(&self, data: Box<fw::types::GpuObject<fw::workqueue::GpuContextData>>);
     /// Check whether the GPU is crashed
     fn is_crashed(&self) -> bool;
+    /// Returns the current device health state.
+    fn state(&self) -> DeviceState;
+    /// Sleep for at most `timeout_ms` until the device is no longer recovering or suspended, and
+    /// return its new state. Returns EAGAIN on timeout, and ERESTARTSYS if interrupted by a
+    /// signal.
+    fn wait_usable(&self, timeout_ms: u32) -> Result<DeviceState>;
+    /// Get the total number of retries caused by full pipe channels.
+    fn pipe_retries(&self) -> u64;
+    /// Take a reference to the GPU manager for an open DRM file.
+    fn file_ref(&self) -> HolderRef;
+    /// Take a reference to the GPU manager for a submission queue.
+    fn queue_ref(&self) -> HolderRef;
+    /// Wait for at most `timeout_ms` until no files, queues or submissions hold on to the GPU
+    /// manager any more. On timeout, logs what is still held and returns `EBUSY`.
+    fn wait_for_holders(&self, timeout_ms: u32) -> Result;
+    /// Free all completed work and collect all freed-but-cached memory in the kernel allocators,
+    /// regardless of the usual garbage thresholds.
+    fn trim_allocators(&self) -> Result;
+    /// Take a snapshot of the raw firmware performance counters (vertex, fragment and compute
+    /// statistics, in that order).
+    fn perf_counters(&self) -> Result<Vec<u8>>;
+    /// Log how many queues have in-flight work, and a summary of each when debugging is enabled.
+    fn dump_in_flight(&self);
+    /// Write a snapshot of all in-flight work across every queue on the device, for debugfs.
+    fn show_in_flight(&self, m: &SeqFile);
+    /// Write the total pipe channel retries and the occupancy of every queue with work in flight,
+    /// for debugfs.
+    fn show_stats(&self, m: &SeqFile);
+    /// Stop accepting new work and wait for all queues to drain and the GPU to power off.
+    ///
+    /// Returns `EBUSY` if the GPU is recovering or does not quiesce in time, in which case it
+    /// keeps running normally.
+    fn suspend(&self) -> Result;
+    /// Accept new work again after `suspend()`, unless the firmware died in the meantime.
+    ///
+    /// `cpu_running` is whether the coprocessor CPU is still running. If it was reset while
+    /// suspended, the firmware state is gone and the device is lost.
+    fn resume(&self, cpu_running: bool) -> Result;
+}
+
+/// Append the raw bytes of a firmware structure to a buffer.
+fn append_raw<T>(buf: &mut Vec<u8>, val: &T) -> Result {
+    // SAFETY: Firmware structures are plain data shared with the GPU, so all of their bytes are
+    // initialized and may be read. The firmware may update them concurrently, which only affects
+    // the consistency of the snapshot.
+    let bytes = unsafe {
+        core::slice::from_raw_parts(val as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    buf.try_extend_from_slice(bytes)?;
+    Ok(())
 }
 
 /// Private generic trait for functions that don't need to escape this module.
@@ -309,6 +517,7 @@ impl rtkit::Operations for GpuManager::ver {
         let dev = &data.dev;
 
         data.crashed.store(true, Ordering::Relaxed);
+        data.update_state(StateEvent::Lost);
 
         if debug_enabled(DebugFlags::OopsOnGpuCrash) {
             panic!("GPU firmware crashed");
@@ -628,6 +837,9 @@ impl GpuManager::ver {
             io_mappings: Vec::new(),
             rtkit <- Mutex::new_named(None, c_str!("rtkit")),
             crashed: AtomicBool::new(false),
+            state: AtomicU32::new(DeviceState::Running as u32),
+            state_lock <- Mutex::new_named((), c_str!("state_lock")),
+            state_cond <- CondVar::new(c_str!("state_cond"), static_lock_class!()),
             event_manager,
             alloc <- Mutex::new_named(alloc, c_str!("alloc")),
             fwctl_channel <- Mutex::new_named(fwctl_channel, c_str!("fwctl_channel")),
@@ -636,7 +848,10 @@ impl GpuManager::ver {
             pipes,
             buffer_mgr: buffer::BufferManager::new()?,
             ids: Default::default(),
-            garbage_work <- Mutex::new_named(Vec::new(), c_str!("garbage_work")),
+            pipe_retries: AtomicU64::new(0),
+            open_files: Arc::try_new(AtomicU32::new(0))?,
+            open_queues: Arc::try_new(AtomicU32::new(0))?,
+            garbage_work: GarbageWork::new(dev)?,
             garbage_contexts <- Mutex::new_named(Vec::new(), c_str!("garbage_contexts")),
         }))?;
 
@@ -823,23 +1038,52 @@ impl GpuManager::ver {
     /// Fetch the GPU MMU fault information from the hardware registers.
     fn get_fault_info(&self) -> Option<regs::FaultInfo> {
         let data = self.dev.data();
-
-        let res = match data.resources() {
-            Some(res) => res,
-            None => {
-                dev_err!(self.dev, "  Failed to acquire resources\n");
-                return None;
-            }
-        };
+        let res = &data.regs;
 
         let info = res.get_fault_info(self.cfg);
         if info.is_some() {
             dev_err!(self.dev, "  Fault info: {:#x?}\n", info.as_ref().unwrap());
+            match res.dump_regs(self.cfg) {
+                Ok(dump) => {
+                    dev_err!(self.dev, "  Register dump:\n");
+                    dev_err!(self.dev, "    CPU_CONTROL: {:#x}\n", dump.cpu_control);
+                    for (name, val) in dump.sgx.iter() {
+                        dev_err!(self.dev, "    {}: {:#x}\n", name, val);
+                    }
+                }
+                Err(e) => dev_err!(self.dev, "  Failed to dump registers: {:?}\n", e),
+            }
         }
         info
     }
 
+    /// Update the device health state for an event, see `DeviceState::next()`.
+    ///
+    /// Returns the new state, or `None` if the event did not change it.
+    fn update_state(&self, event: StateEvent) -> Option<DeviceState> {
+        let ret = self
+            .state
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |old| {
+                DeviceState::from_raw(old).next(event).map(|s| s as u32)
+            })
+            .ok()
+            .and_then(|old| DeviceState::from_raw(old).next(event));
+        self.notify_state();
+        ret
+    }
+
+    /// Wake up everyone waiting for a device state change in `wait_usable()`.
+    fn notify_state(&self) {
+        // Taking the lock orders this against waiters that saw the old state but are not asleep
+        // yet, so they cannot miss the wakeup.
+        let _guard = self.state_lock.lock();
+        self.state_cond.notify_all();
+    }
+
     /// Resume the GPU firmware after it halts (due to a timeout, fault, or request).
+    ///
+    /// The caller must have sent the `Halted` event when it noticed the halt. This ends the
+    /// recovery window by sending `Recovered` or `Lost`.
     fn recover(&self) {
         self.initdata.fw_status.with(|raw, _inner| {
             let halt_count = raw.flags.halt_count.load(Ordering::Relaxed);
@@ -861,12 +1105,15 @@ impl GpuManager::ver {
 
             if debug_enabled(DebugFlags::NoGpuRecovery) {
                 dev_crit!(self.dev, "  GPU recovery is disabled, wedging forever!\n");
+                self.update_state(StateEvent::Lost);
             } else if halted != 0 {
                 dev_err!(self.dev, "  Attempting recovery...\n");
                 raw.flags.halted.store(0, Ordering::SeqCst);
                 raw.flags.resume.store(1, Ordering::SeqCst);
+                self.update_state(StateEvent::Recovered);
             } else {
                 dev_err!(self.dev, "  Cannot recover.\n");
+                self.update_state(StateEvent::Lost);
             }
         });
     }
@@ -891,11 +1138,32 @@ impl GpuManager::ver {
             PipeType::Compute => &self.pipes.comp,
         };
 
+        if debug_enabled(DebugFlags::ValidatePointers) {
+            job.validate(self.uat.kernel_vm())?;
+        }
+
         let index: usize = job.priority() as usize;
         let mut pipe = pipes.get(index).ok_or(EIO)?.lock();
 
+        let max_retries = {
+            let lock = crate::THIS_MODULE.kernel_param_lock();
+            *crate::submit_retries.read(&lock)
+        };
+
         mod_dev_dbg!(self.dev, "GPU: run_job: run()\n");
-        job.run(&mut pipe);
+        let retries = job.run(&mut pipe, max_retries)?;
+        if retries > 0 {
+            let total = self
+                .pipe_retries
+                .fetch_add(retries as u64, Ordering::Relaxed)
+                + retries as u64;
+            mod_dev_dbg!(
+                self.dev,
+                "GPU: run_job: pipe full, needed {} retries ({} total)\n",
+                retries,
+                total
+            );
+        }
         mod_dev_dbg!(self.dev, "GPU: run_job: ring doorbell\n");
 
         let mut guard = self.rtkit.lock();
@@ -904,11 +1172,70 @@ impl GpuManager::ver {
             EP_DOORBELL,
             MSG_TX_DOORBELL | pipe_type as u64 | ((index as u64) << 2),
         )?;
+        core::mem::drop(guard);
+        core::mem::drop(pipe);
         mod_dev_dbg!(self.dev, "GPU: run_job: done\n");
 
+        if debug_enabled(DebugFlags::PollCompletion) {
+            self.poll_completion();
+        }
+
         Ok(())
     }
 
+    /// Log the layout of the InitData structure tree handed to the firmware.
+    ///
+    /// This is useful when bringing up new SoCs, to confirm that the firmware structures were set
+    /// up where expected.
+    fn dump_initdata(&self) {
+        let initdata = &self.initdata;
+        let rp = &initdata.runtime_pointers;
+
+        let layout = [
+            ("InitData", initdata.gpu_va()),
+            ("  RuntimePointers", rp.gpu_va()),
+            ("    HwDataA", rp.hwdata_a.gpu_va()),
+            ("    HwDataB", rp.hwdata_b.gpu_va()),
+            ("    Stats (vtx)", rp.stats.vtx.gpu_va()),
+            ("    Stats (frag)", rp.stats.frag.gpu_va()),
+            ("    Stats (comp)", rp.stats.comp.gpu_va()),
+            ("    BufferMgrCtl", rp.buffer_mgr_ctl.gpu_va()),
+            ("  Globals", initdata.globals.gpu_va()),
+            ("  FwStatus", initdata.fw_status.gpu_va()),
+        ];
+
+        for (name, va) in layout {
+            dev_info!(self.dev, "{} @ {:#x}\n", name, va.get());
+        }
+
+        self.initdata.fw_status.with(|raw, _inner| {
+            dev_info!(
+                self.dev,
+                "  FwStatus: halt_count={} halted={}\n",
+                raw.flags.halt_count.load(Ordering::Relaxed),
+                raw.flags.halted.load(Ordering::Relaxed)
+            );
+        });
+    }
+
+    /// Poll event stamps directly until all work completes or a timeout expires.
+    ///
+    /// This is a debugging aid for bringup, when the firmware event signaling path is broken and
+    /// waiters would otherwise hang forever.
+    fn poll_completion(&self) {
+        let timeout = time::ktime_get() + Duration::from_millis(POLL_COMPLETION_TIMEOUT_MS);
+
+        while !self.event_manager.poll_all() {
+            if time::ktime_get() > timeout {
+                dev_warn!(self.dev, "GPU: Timed out polling for completion\n");
+                return;
+            }
+            coarse_sleep(Duration::from_millis(1));
+        }
+
+        mod_dev_dbg!(self.dev, "GPU: Polled all work to completion\n");
+    }
+
     pub(crate) fn start_op(self: &Arc<GpuManager::ver>) -> Result<OpGuard> {
         if self.is_crashed() {
             return Err(ENODEV);
@@ -1001,6 +1328,10 @@ impl GpuManager for GpuManager::ver {
     }
 
     fn init(&self) -> Result {
+        if debug_enabled(DebugFlags::DumpInitData) {
+            self.dump_initdata();
+        }
+
         self.tx_channels.lock().device_control.send(
             &fw::channels::DeviceControlMsg::ver::Initialize(Default::default()),
         );
@@ -1034,12 +1365,6 @@ impl GpuManager for GpuManager::ver {
     }
 
     fn alloc(&self) -> Guard<'_, KernelAllocators, MutexBackend> {
-        /*
-         * TODO: This should be done in a workqueue or something.
-         * Clean up completed jobs
-         */
-        self.garbage_work.lock().clear();
-
         /* Clean up idle contexts */
         let mut garbage_ctx = Vec::new();
         core::mem::swap(&mut *self.garbage_contexts.lock(), &mut garbage_ctx);
@@ -1189,6 +1514,10 @@ impl GpuManager for GpuManager::ver {
         dev_err!(self.dev, "  Event slot: {}\n", event_slot);
         dev_err!(self.dev, "  Timeout count: {}\n", counter);
 
+        // Hold off new work until the pending work has been failed and the firmware resumed.
+        self.update_state(StateEvent::Halted);
+        self.dump_in_flight();
+
         // If we have fault info, consider it a fault.
         let error = match self.get_fault_info() {
             Some(info) => workqueue::WorkError::Fault(info),
@@ -1206,6 +1535,7 @@ impl GpuManager for GpuManager::ver {
         dev_err!(self.dev, ".'|  _-_-  |'.\n");
         dev_err!(self.dev, "  |________|  \n");
         dev_err!(self.dev, "GPU fault nya~!!!!!\n");
+        self.update_state(StateEvent::Halted);
         let error = match self.get_fault_info() {
             Some(info) => workqueue::WorkError::Fault(info),
             None => workqueue::WorkError::Unknown,
@@ -1214,6 +1544,20 @@ impl GpuManager for GpuManager::ver {
         self.recover();
     }
 
+    fn handle_ring_error(&self, pipe_type: PipeType) {
+        dev_err!(
+            self.dev,
+            "GPU: {:?} work queue ring is corrupted\n",
+            pipe_type
+        );
+        // The ring cannot be trusted any more, so treat it like a fault we have no info for: fail
+        // everything in flight and try to resume the firmware, or give up on the device.
+        self.update_state(StateEvent::Halted);
+        self.dump_in_flight();
+        self.mark_pending_events(None, workqueue::WorkError::Unknown);
+        self.recover();
+    }
+
     fn wait_for_poweroff(&self, timeout: usize) -> Result {
         self.initdata.runtime_pointers.hwdata_a.with(|raw, _inner| {
             for _i in 0..timeout {
@@ -1251,23 +1595,43 @@ impl GpuManager for GpuManager::ver {
     }
 
     fn add_completed_work(&self, work: Vec<Box<dyn workqueue::GenSubmittedWork>>) {
-        let mut garbage = self.garbage_work.lock();
+        let batch = {
+            let lock = crate::THIS_MODULE.kernel_param_lock();
+            *crate::deferred_free_batch.read(&lock)
+        };
 
-        if garbage.try_reserve(work.len()).is_err() {
-            dev_err!(
-                self.dev,
-                "Failed to reserve space for completed work, deadlock possible.\n"
-            );
-            return;
-        }
+        // Work is only handed off here once the GPU has signaled its completion, and the firmware
+        // allocations it owns still go through the allocator garbage collection, so nothing is
+        // reused before the GPU is done with it.
+        let pending = {
+            let mut garbage = self.garbage_work.items.lock();
+
+            if garbage.try_reserve(work.len()).is_err() {
+                dev_err!(
+                    self.dev,
+                    "Failed to reserve space for completed work, deadlock possible.\n"
+                );
+                return;
+            }
+
+            for i in work {
+                garbage
+                    .try_push(i)
+                    .expect("try_push() failed after try_reserve()");
+            }
+            garbage.len()
+        };
 
-        for i in work {
-            garbage
-                .try_push(i)
-                .expect("try_push() failed after try_reserve()");
+        if pending >= (batch as usize).max(1) {
+            Work::enqueue(self.garbage_work.clone());
         }
     }
 
+    fn free_garbage(&self) {
+        self.garbage_work.work.flush();
+        self.garbage_work.free();
+    }
+
     fn free_context(&self, ctx: Box<fw::types::GpuObject<fw::workqueue::GpuContextData>>) {
         let mut garbage = self.garbage_contexts.lock();
 
@@ -1282,6 +1646,181 @@ impl GpuManager for GpuManager::ver {
     fn is_crashed(&self) -> bool {
         self.crashed.load(Ordering::Relaxed)
     }
+
+    fn state(&self) -> DeviceState {
+        DeviceState::from_raw(self.state.load(Ordering::Acquire))
+    }
+
+    fn wait_usable(&self, timeout_ms: u32) -> Result<DeviceState> {
+        let mut guard = self.state_lock.lock();
+        let mut remaining = time::msecs_to_jiffies(timeout_ms);
+        loop {
+            // Always decide based on the state we woke up with, even if the timeout expired at
+            // the same time.
+            let state = self.state();
+            if !matches!(state, DeviceState::Recovering | DeviceState::Suspended) {
+                return Ok(state);
+            }
+            if remaining == 0 {
+                return Err(EAGAIN);
+            }
+
+            remaining = match self.state_cond.wait_timeout(&mut guard, remaining) {
+                CondVarTimeoutResult::Timeout => 0,
+                CondVarTimeoutResult::Woken { jiffies } => jiffies,
+                CondVarTimeoutResult::Signal { .. } => return Err(ERESTARTSYS),
+            };
+        }
+    }
+
+    fn pipe_retries(&self) -> u64 {
+        self.pipe_retries.load(Ordering::Relaxed)
+    }
+
+    fn file_ref(&self) -> HolderRef {
+        HolderRef::new(&self.open_files)
+    }
+
+    fn queue_ref(&self) -> HolderRef {
+        HolderRef::new(&self.open_queues)
+    }
+
+    fn wait_for_holders(&self, timeout_ms: u32) -> Result {
+        let mut files = 0;
+        let mut queues = 0;
+        let mut submissions = 0;
+
+        for _i in 0..timeout_ms {
+            files = self.open_files.load(Ordering::Acquire);
+            queues = self.open_queues.load(Ordering::Acquire);
+            submissions = self
+                .initdata
+                .globals
+                .with(|raw, _inner| raw.pending_submissions.load(Ordering::Acquire));
+
+            if files == 0 && queues == 0 && submissions == 0 {
+                return Ok(());
+            }
+            coarse_sleep(Duration::from_millis(1));
+        }
+
+        dev_warn!(
+            self.dev,
+            "GPU: Still in use after {} ms: {} open files, {} queues, {} submissions in flight\n",
+            timeout_ms,
+            files,
+            queues,
+            submissions
+        );
+        Err(EBUSY)
+    }
+
+    fn trim_allocators(&self) -> Result {
+        // Free all completed work, whatever the batch threshold, so its memory is garbage too.
+        self.free_garbage();
+
+        // Acquiring the allocators also invalidates and frees idle contexts.
+        let mut guard = self.alloc();
+
+        // Freed firmware objects may still be in the coprocessor cache.
+        self.flush_fw_cache()?;
+
+        let alloc = &mut *guard;
+        for a in [
+            &mut alloc.private,
+            &mut alloc.shared,
+            &mut alloc.shared_ro,
+            &mut alloc.gpu,
+            &mut alloc.gpu_ro,
+            &mut alloc.gpu_low,
+        ] {
+            let (garbage_count, garbage_bytes) = a.garbage();
+            if garbage_count > 0 {
+                mod_dev_dbg!(
+                    self.dev,
+                    "Trimming {} garbage objects ({} bytes)\n",
+                    garbage_count,
+                    garbage_bytes
+                );
+                a.collect_garbage(garbage_count);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn perf_counters(&self) -> Result<Vec<u8>> {
+        let stats = &self.initdata.runtime_pointers.stats;
+        let mut buf = Vec::new();
+
+        stats.vtx.with(|raw, _inner| append_raw(&mut buf, raw))?;
+        stats.frag.with(|raw, _inner| append_raw(&mut buf, raw))?;
+        stats.comp.with(|raw, _inner| append_raw(&mut buf, raw))?;
+
+        Ok(buf)
+    }
+
+    fn dump_in_flight(&self) {
+        let busy = self.event_manager.dump_all();
+        dev_info!(self.dev, "GPU: {} queues with work in flight\n", busy);
+    }
+
+    fn show_in_flight(&self, m: &SeqFile) {
+        self.event_manager.show_all(m);
+    }
+
+    fn show_stats(&self, m: &SeqFile) {
+        seq_print!(m, "pipe_retries={}\n", self.pipe_retries());
+        self.event_manager.show_stats(m);
+    }
+
+    fn suspend(&self) -> Result {
+        if self.update_state(StateEvent::Suspend).is_none() {
+            match self.state() {
+                // Nothing can run on a lost device, so there is nothing to quiesce.
+                DeviceState::Lost => return Ok(()),
+                state => {
+                    dev_err!(self.dev, "GPU: Cannot suspend in state {:?}\n", state);
+                    return Err(EBUSY);
+                }
+            }
+        }
+
+        let ret = self
+            .event_manager
+            .wait_idle_all(SUSPEND_IDLE_TIMEOUT_MS)
+            .and_then(|_| self.wait_for_poweroff(SUSPEND_IDLE_TIMEOUT_MS as usize));
+
+        if let Err(e) = ret {
+            dev_err!(self.dev, "GPU: Failed to quiesce for suspend: {:?}\n", e);
+            self.dump_in_flight();
+            self.update_state(StateEvent::Resume);
+            return Err(EBUSY);
+        }
+
+        mod_dev_dbg!(self.dev, "GPU: Suspended\n");
+        Ok(())
+    }
+
+    fn resume(&self, cpu_running: bool) -> Result {
+        if self.is_crashed() {
+            dev_err!(self.dev, "GPU: Firmware crashed while suspended\n");
+            self.update_state(StateEvent::Lost);
+            return Err(ENODEV);
+        }
+
+        if !cpu_running {
+            dev_err!(self.dev, "GPU: Coprocessor was reset while suspended\n");
+            self.update_state(StateEvent::Lost);
+            return Err(ENODEV);
+        }
+
+        // Only undo our own suspend, a lost device stays lost.
+        self.update_state(StateEvent::Resume);
+
+        mod_dev_dbg!(self.dev, "GPU: Resumed\n");
+        Ok(())
+    }
 }
 
 #[versions(AGX)]
@@ -1295,3 +1834,64 @@ impl GpuManagerPriv for GpuManager::ver {
         mod_dev_dbg!(self.dev, "OP end (pending: {})\n", val - 1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceState, StateEvent};
+
+    #[test]
+    fn test_recovery() {
+        assert_eq!(
+            DeviceState::Running.next(StateEvent::Halted),
+            Some(DeviceState::Recovering)
+        );
+        assert_eq!(
+            DeviceState::Recovering.next(StateEvent::Recovered),
+            Some(DeviceState::Running)
+        );
+        assert_eq!(
+            DeviceState::Recovering.next(StateEvent::Lost),
+            Some(DeviceState::Lost)
+        );
+    }
+
+    #[test]
+    fn test_lost_is_final() {
+        for event in [
+            StateEvent::Halted,
+            StateEvent::Recovered,
+            StateEvent::Lost,
+            StateEvent::Suspend,
+            StateEvent::Resume,
+        ] {
+            assert_eq!(DeviceState::Lost.next(event), None);
+        }
+    }
+
+    #[test]
+    fn test_suspend() {
+        assert_eq!(
+            DeviceState::Running.next(StateEvent::Suspend),
+            Some(DeviceState::Suspended)
+        );
+        assert_eq!(DeviceState::Recovering.next(StateEvent::Suspend), None);
+        assert_eq!(DeviceState::Suspended.next(StateEvent::Suspend), None);
+        assert_eq!(
+            DeviceState::Suspended.next(StateEvent::Resume),
+            Some(DeviceState::Running)
+        );
+        assert_eq!(DeviceState::Running.next(StateEvent::Resume), None);
+        assert_eq!(DeviceState::Recovering.next(StateEvent::Resume), None);
+    }
+
+    #[test]
+    fn test_fault_while_suspended() {
+        // Recovering from a fault must not let new work in before resume.
+        assert_eq!(DeviceState::Suspended.next(StateEvent::Halted), None);
+        assert_eq!(DeviceState::Suspended.next(StateEvent::Recovered), None);
+        assert_eq!(
+            DeviceState::Suspended.next(StateEvent::Lost),
+            Some(DeviceState::Lost)
+        );
+    }
+}
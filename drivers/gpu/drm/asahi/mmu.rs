@@ -66,7 +66,7 @@ pub(crate) const UAT_IAS: usize = 39;
 pub(crate) const UAT_IAS_KERN: usize = 36;
 
 /// Lower/user base VA
-const IOVA_USER_BASE: usize = UAT_PGSZ;
+pub(crate) const IOVA_USER_BASE: usize = UAT_PGSZ;
 /// Lower/user top VA
 const IOVA_USER_TOP: usize = (1 << UAT_IAS) - 1;
 /// Upper/kernel base VA
@@ -188,6 +188,8 @@ struct VmInner {
     mm: mm::Allocator<(), MappingInner>,
     uat_inner: Arc<UatInner>,
     active_users: usize,
+    /// Total size of all live mappings in this Vm, excluding guard pages.
+    mapped_bytes: usize,
     binding: Option<slotalloc::Guard<SlotInner>>,
     bind_token: Option<slotalloc::SlotToken>,
     id: u64,
@@ -270,13 +272,29 @@ impl VmInner {
     }
 
     /// Map an `mm::Node` representing an mapping in VA space.
+    ///
+    /// Only the `mapped_size` bytes of the `SGTable` starting at `offset` are mapped.
     fn map_node(&mut self, node: &mm::Node<(), MappingInner>, prot: u32) -> Result {
         let mut iova = node.start() as usize;
         let sgt = node.sgt.as_ref().ok_or(EINVAL)?;
+        let mut skip = node.offset;
+        let mut left = node.mapped_size;
 
         for range in sgt.iter() {
-            let addr = range.dma_address();
-            let len = range.dma_len();
+            if left == 0 {
+                break;
+            }
+
+            let mut addr = range.dma_address();
+            let mut len = range.dma_len();
+
+            if skip >= len {
+                skip -= len;
+                continue;
+            }
+            addr += skip;
+            len = (len - skip).min(left);
+            skip = 0;
 
             if (addr | len | iova) & UAT_PGMSK != 0 {
                 dev_err!(
@@ -300,7 +318,20 @@ impl VmInner {
             self.map_pages(iova, addr, UAT_PGSZ, len >> UAT_PGBIT, prot)?;
 
             iova += len;
+            left -= len;
         }
+
+        if left != 0 {
+            dev_err!(
+                self.dev,
+                "MMU: SGTable too small for mapping {:#x}:{:#x} at offset {:#x}\n",
+                node.start(),
+                node.mapped_size,
+                node.offset
+            );
+            return Err(EINVAL);
+        }
+
         Ok(())
     }
 }
@@ -363,6 +394,8 @@ pub(crate) struct MappingInner {
     uat_inner: Arc<UatInner>,
     prot: u32,
     mapped_size: usize,
+    /// Offset into `sgt` at which the mapped range starts.
+    offset: usize,
     sgt: Option<gem::SGTable>,
 }
 
@@ -380,6 +413,17 @@ impl Mapping {
         self.0.mapped_size
     }
 
+    /// Returns whether this mapping allows GPU writes
+    pub(crate) fn is_writable(&self) -> bool {
+        self.0.prot & prot::WRITE != 0
+    }
+
+    /// Returns whether the owning [`Vm`] is currently bound to a slot, which means there may be
+    /// GPU work in flight that uses this mapping.
+    pub(crate) fn vm_active(&self) -> bool {
+        self.0.owner.lock().active_users != 0
+    }
+
     /// Remap a cached mapping as uncached, then synchronously flush that range of VAs from the
     /// coprocessor cache. This is required to safely unmap cached/private mappings.
     fn remap_uncached_and_flush(&mut self) {
@@ -555,6 +599,8 @@ impl Drop for Mapping {
             );
         }
 
+        owner.mapped_bytes -= self.size();
+
         if let Some(asid) = owner.slot() {
             mem::tlbi_range(asid as u8, self.iova(), self.size());
             mod_dev_dbg!(
@@ -810,6 +856,7 @@ impl Vm {
                     binding: None,
                     bind_token: None,
                     active_users: 0,
+                    mapped_bytes: 0,
                     id,
                 },
                 c_str!("VmInner"),
@@ -834,11 +881,13 @@ impl Vm {
                 prot: PROT_FW_SHARED_RW,
                 sgt: Some(sgt),
                 mapped_size: size,
+                offset: 0,
             },
             (size + UAT_PGSZ) as u64, // Add guard page
         )?;
 
         inner.map_node(&node, PROT_FW_SHARED_RW)?;
+        inner.mapped_bytes += size;
         Ok(Mapping(node))
     }
 
@@ -852,7 +901,7 @@ impl Vm {
         start: u64,
         end: u64,
         prot: u32,
-        guard: bool,
+        guard_pages: usize,
     ) -> Result<Mapping> {
         let mut inner = self.inner.lock();
 
@@ -864,8 +913,9 @@ impl Vm {
                 prot,
                 sgt: Some(sgt),
                 mapped_size: size,
+                offset: 0,
             },
-            (size + if guard { UAT_PGSZ } else { 0 }) as u64, // Add guard page
+            (size + guard_pages * UAT_PGSZ) as u64, // Add guard pages
             alignment,
             0,
             start,
@@ -874,18 +924,22 @@ impl Vm {
         )?;
 
         inner.map_node(&node, prot)?;
+        inner.mapped_bytes += size;
         Ok(Mapping(node))
     }
 
-    /// Map a GEM object (using its `SGTable`) into this Vm at a specific address.
+    /// Map a range of a GEM object (using its `SGTable`) into this Vm at a specific address.
+    ///
+    /// `size` bytes starting at `offset` into the object are mapped.
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn map_at(
         &self,
         addr: u64,
         size: usize,
         sgt: gem::SGTable,
+        offset: usize,
         prot: u32,
-        guard: bool,
+        guard_pages: usize,
     ) -> Result<Mapping> {
         let mut inner = self.inner.lock();
 
@@ -897,13 +951,15 @@ impl Vm {
                 prot,
                 sgt: Some(sgt),
                 mapped_size: size,
+                offset,
             },
             addr,
-            (size + if guard { UAT_PGSZ } else { 0 }) as u64, // Add guard page
+            (size + guard_pages * UAT_PGSZ) as u64, // Add guard pages
             0,
         )?;
 
         inner.map_node(&node, prot)?;
+        inner.mapped_bytes += size;
         Ok(Mapping(node))
     }
 
@@ -919,6 +975,7 @@ impl Vm {
                 prot,
                 sgt: None,
                 mapped_size: size,
+                offset: 0,
             },
             (size + UAT_PGSZ) as u64, // Add guard page
             UAT_PGSZ as u64,
@@ -950,10 +1007,30 @@ impl Vm {
         );
 
         inner.map_pages(iova, phys, UAT_PGSZ, size >> UAT_PGBIT, prot)?;
+        inner.mapped_bytes += size;
 
         Ok(Mapping(node))
     }
 
+    /// Returns whether a given IOVA is currently backed by a page table entry in this Vm.
+    ///
+    /// This walks the page tables, so it is only intended for debugging.
+    pub(crate) fn is_mapped(&self, iova: usize) -> bool {
+        let mut inner = self.inner.lock();
+
+        match inner.map_iova(iova, 1) {
+            Ok(mapped_iova) => inner.page_table.iova_to_phys(mapped_iova).is_some(),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the total number of bytes currently mapped in this Vm, excluding guard pages.
+    ///
+    /// This includes driver-internal mappings as well as user mappings.
+    pub(crate) fn mapped_bytes(&self) -> usize {
+        self.inner.lock().mapped_bytes
+    }
+
     /// Returns the unique ID of this Vm
     pub(crate) fn id(&self) -> u64 {
         self.id
@@ -963,19 +1040,35 @@ impl Vm {
     pub(crate) fn file_id(&self) -> u64 {
         self.file_id
     }
-}
 
-impl Drop for VmInner {
-    fn drop(&mut self) {
-        assert_eq!(self.active_users, 0);
+    /// Eagerly give up the TTB slot this Vm was last bound to, flushing its translations.
+    ///
+    /// This normally happens when the `VmInner` is dropped, which may be much later than the
+    /// owner is done with the Vm, since mappings keep it alive. Returns `EBUSY` if the Vm is
+    /// currently bound for GPU work.
+    pub(crate) fn release_slot(&self) -> Result {
+        let mut inner = self.inner.lock();
+
+        if inner.active_users != 0 {
+            return Err(EBUSY);
+        }
 
         mod_pr_debug!(
-            "VmInner::Drop [{}]: bind_token={:?}\n",
+            "Vm::release_slot [{}]: bind_token={:?}\n",
             self.id,
-            self.bind_token
+            inner.bind_token
         );
+        inner.release_slot();
+        Ok(())
+    }
+}
 
-        // Make sure this VM is not mapped to a TTB if it was
+impl VmInner {
+    /// Unmap this VM from the TTB slot it was last bound to, if it still owns it, and invalidate
+    /// that ASID so no stale translations survive for the next `Vm` to use the slot.
+    ///
+    /// Must only be called while the VM is not bound (no active users).
+    fn release_slot(&mut self) {
         if let Some(token) = self.bind_token.take() {
             let idx = (token.last_slot() as usize) + UAT_USER_CTX_START;
             let ttb = self.ttb() | TTBR_VALID | (idx as u64) << TTBR_ASID_SHIFT;
@@ -1002,7 +1095,7 @@ impl Drop for VmInner {
             // well play it safe and invalidate the whole ASID.
             if inval {
                 mod_pr_debug!(
-                    "VmInner::Drop [{}]: need inval for ASID {:#x}\n",
+                    "VmInner::release_slot [{}]: need inval for ASID {:#x}\n",
                     self.id,
                     idx
                 );
@@ -1013,6 +1106,21 @@ impl Drop for VmInner {
     }
 }
 
+impl Drop for VmInner {
+    fn drop(&mut self) {
+        assert_eq!(self.active_users, 0);
+
+        mod_pr_debug!(
+            "VmInner::Drop [{}]: bind_token={:?}\n",
+            self.id,
+            self.bind_token
+        );
+
+        // Make sure this VM is not mapped to a TTB if it was
+        self.release_slot();
+    }
+}
+
 impl Uat {
     /// Map a bootloader-preallocated memory region
     fn map_region(
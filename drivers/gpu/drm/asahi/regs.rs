@@ -35,6 +35,44 @@ const CORE_MASKS_G14X: usize = 0xe01500;
 const FAULT_INFO_G14X: usize = 0xd8c0;
 const FAULT_ADDR_G14X: usize = 0xd8c8;
 
+/// An SGX register captured by `Resources::dump_regs()`.
+struct DumpReg {
+    name: &'static str,
+    offset: usize,
+    /// Whether this is a 64-bit register.
+    wide: bool,
+}
+
+const fn dump_reg(name: &'static str, offset: usize, wide: bool) -> DumpReg {
+    DumpReg { name, offset, wide }
+}
+
+/// SGX registers captured on all GPUs.
+const DUMP_REGS: &[DumpReg] = &[
+    dump_reg("ID_VERSION", ID_VERSION, false),
+    dump_reg("ID_UNK08", ID_UNK08, false),
+    dump_reg("ID_COUNTS_1", ID_COUNTS_1, false),
+    dump_reg("ID_COUNTS_2", ID_COUNTS_2, false),
+    dump_reg("ID_UNK18", ID_UNK18, false),
+    dump_reg("ID_CLUSTERS", ID_CLUSTERS, false),
+];
+
+/// SGX registers captured only on pre-G14X GPUs.
+const DUMP_REGS_LEGACY: &[DumpReg] = &[
+    dump_reg("FAULT_INFO", FAULT_INFO, true),
+    dump_reg("CORE_MASK_0", CORE_MASK_0, false),
+    dump_reg("CORE_MASK_1", CORE_MASK_1, false),
+];
+
+/// SGX registers captured only on G14X (and later) GPUs.
+const DUMP_REGS_G14X: &[DumpReg] = &[
+    dump_reg("FAULT_INFO", FAULT_INFO_G14X, true),
+    dump_reg("FAULT_ADDR", FAULT_ADDR_G14X, true),
+    dump_reg("CORE_MASKS[0]", CORE_MASKS_G14X, false),
+    dump_reg("CORE_MASKS[1]", CORE_MASKS_G14X + 4, false),
+    dump_reg("CORE_MASKS[2]", CORE_MASKS_G14X + 8, false),
+];
+
 /// Enum representing the unit that caused an MMU fault.
 #[allow(non_camel_case_types)]
 #[allow(clippy::upper_case_acronyms)]
@@ -140,7 +178,19 @@ pub(crate) struct FaultInfo {
     pub(crate) reason: FaultReason,
 }
 
+/// A snapshot of GPU MMIO register state, for post-fault diagnosis.
+#[derive(Debug)]
+pub(crate) struct RegDump {
+    /// ASC coprocessor control register.
+    pub(crate) cpu_control: u32,
+    /// SGX registers as (name, value) pairs.
+    pub(crate) sgx: Vec<(&'static str, u64)>,
+}
+
 /// Device resources for this GPU instance.
+///
+/// Dropping this stops the ASC coprocessor CPU, so it must outlive everything that talks to the
+/// firmware.
 pub(crate) struct Resources {
     dev: device::Device,
     asc: IoMem<ASC_CTL_SIZE>,
@@ -198,6 +248,20 @@ impl Resources {
         Ok(())
     }
 
+    /// Stop the ASC coprocessor CPU.
+    pub(crate) fn stop_cpu(&self) -> Result {
+        let val = self.asc.readl_relaxed(CPU_CONTROL);
+
+        self.asc.writel_relaxed(val & !CPU_RUN, CPU_CONTROL);
+
+        Ok(())
+    }
+
+    /// Returns whether the ASC coprocessor CPU is running.
+    pub(crate) fn cpu_running(&self) -> bool {
+        self.asc.readl_relaxed(CPU_CONTROL) & CPU_RUN != 0
+    }
+
     /// Get the GPU identification info from registers.
     ///
     /// See [`hw::GpuIdConfig`] for the result.
@@ -344,6 +408,33 @@ impl Resources {
         })
     }
 
+    /// Capture the registers listed in `DUMP_REGS` (plus the generation-specific list) and the
+    /// ASC control register. This only reads status registers, so it is safe to call at any
+    /// time, including after a fault.
+    pub(crate) fn dump_regs(&self, cfg: &'static hw::HwConfig) -> Result<RegDump> {
+        let g14x = cfg.gpu_core as u32 >= hw::GpuCore::G14S as u32;
+        let extra = if g14x {
+            DUMP_REGS_G14X
+        } else {
+            DUMP_REGS_LEGACY
+        };
+
+        let mut sgx = Vec::try_with_capacity(DUMP_REGS.len() + extra.len())?;
+        for reg in DUMP_REGS.iter().chain(extra.iter()) {
+            let val = if reg.wide {
+                self.sgx_read64(reg.offset)
+            } else {
+                self.sgx_read32(reg.offset) as u64
+            };
+            sgx.try_push((reg.name, val))?;
+        }
+
+        Ok(RegDump {
+            cpu_control: self.asc.readl_relaxed(CPU_CONTROL),
+            sgx,
+        })
+    }
+
     /// Get the fault information from the MMU status register, if one occurred.
     pub(crate) fn get_fault_info(&self, cfg: &'static hw::HwConfig) -> Option<FaultInfo> {
         let g14x = cfg.gpu_core as u32 >= hw::GpuCore::G14S as u32;
@@ -466,3 +557,10 @@ impl Resources {
         })
     }
 }
+
+impl Drop for Resources {
+    fn drop(&mut self) {
+        dev_info!(self.dev, "Stopping coprocessor CPU\n");
+        let _ = self.stop_cpu();
+    }
+}
@@ -226,8 +226,9 @@ impl<T: SlotItem> SlotAllocator<T> {
             if oldest_time == u64::MAX {
                 if first {
                     pr_warn!(
-                        "{}: out of slots, blocking\n",
-                        core::any::type_name::<Self>()
+                        "{}: out of slots ({} total), blocking\n",
+                        core::any::type_name::<Self>(),
+                        inner.slots.len()
                     );
                 }
                 first = false;
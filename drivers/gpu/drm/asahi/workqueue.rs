@@ -21,23 +21,76 @@ use crate::no_debug;
 use crate::object::OpaqueGpuObject;
 use crate::regs::FaultReason;
 use crate::{channel, driver, event, fw, gpu, object, regs};
+use core::fmt;
 use core::num::NonZeroU64;
 use core::sync::atomic::Ordering;
+use core::time::Duration;
 use kernel::{
     c_str,
+    delay::coarse_sleep,
     error::code::*,
     prelude::*,
+    seq_file::SeqFile,
+    seq_print, static_lock_class,
     sync::{
         lock::{mutex::MutexBackend, Guard},
-        Arc, Mutex,
+        Arc, CondVar, CondVarTimeoutResult, Mutex,
     },
-    uapi,
+    time, uapi,
 };
 
 const DEBUG_CLASS: DebugFlags = DebugFlags::WorkQueue;
 
 const MAX_JOB_SLOTS: u32 = 127;
 
+/// Smallest supported ring size. One entry always stays empty to tell a full ring from an empty
+/// one, so anything smaller cannot hold a command.
+const MIN_RING_SIZE: u32 = 2;
+/// Largest supported ring size, to bound the shared memory a single queue can allocate.
+const MAX_RING_SIZE: u32 = 0x4000;
+
+/// Delay between retries when a pipe channel is full.
+const SUBMIT_RETRY_DELAY_MS: u64 = 1;
+
+/// Returns how many entries a ring of `size` entries advances to get from `from` to `to`.
+///
+/// Both pointers must be in range.
+fn ring_distance(from: u32, to: u32, size: u32) -> u32 {
+    if to >= from {
+        to - from
+    } else {
+        size - from + to
+    }
+}
+
+/// Validates a done pointer read back from the firmware against the driver's ring state.
+///
+/// The firmware only ever moves the done pointer forward (modulo the ring size), and never past
+/// our write pointer. Returns `None` if `doneptr` is out of range, moved backwards from
+/// `last_doneptr` or overtook `wptr`.
+fn validate_doneptr(doneptr: u32, last_doneptr: u32, wptr: u32, size: u32) -> Option<u32> {
+    // Range check first, the distance math below is only meaningful for in-range pointers.
+    if doneptr >= size || last_doneptr >= size || wptr >= size {
+        return None;
+    }
+
+    let advance = ring_distance(last_doneptr, doneptr, size);
+    let in_flight = ring_distance(last_doneptr, wptr, size);
+
+    if advance > in_flight {
+        return None;
+    }
+
+    Some(doneptr)
+}
+
+/// Returns the number of queue priority levels supported by the firmware.
+///
+/// Valid queue priorities are `0..num_priorities()`, indexing into the firmware priority table.
+pub(crate) fn num_priorities() -> u32 {
+    raw::PRIORITY.len() as u32
+}
+
 /// An enum of possible errors that might cause a piece of work to fail execution.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub(crate) enum WorkError {
@@ -49,10 +102,36 @@ pub(crate) enum WorkError {
     Killed,
     /// The GPU crashed.
     NoDevice,
+    /// The driver ran out of memory while building the work.
+    OutOfMemory,
     /// Unknown reason.
     Unknown,
 }
 
+impl WorkError {
+    /// Build the `WorkError` for an error injected via the `InjectError` debug flag, for a
+    /// command running on a given VM slot.
+    fn injected(kind: InjectedError, vm_slot: u32) -> WorkError {
+        match kind {
+            InjectedError::Timeout => WorkError::Timeout,
+            InjectedError::Fault => WorkError::Fault(regs::FaultInfo {
+                address: 0,
+                sideband: 0,
+                vm_slot,
+                unit_code: 0,
+                unit: regs::FaultUnit::Unknown(0),
+                level: 0,
+                unk_5: 0,
+                read: false,
+                reason: FaultReason::Unmapped,
+            }),
+            InjectedError::Killed => WorkError::Killed,
+            InjectedError::NoDevice => WorkError::NoDevice,
+            InjectedError::OutOfMemory => WorkError::OutOfMemory,
+        }
+    }
+}
+
 impl From<WorkError> for uapi::drm_asahi_result_info {
     fn from(err: WorkError) -> Self {
         match err {
@@ -79,6 +158,7 @@ impl From<WorkError> for uapi::drm_asahi_result_info {
                     WorkError::Timeout => uapi::drm_asahi_status_DRM_ASAHI_STATUS_TIMEOUT,
                     WorkError::Killed => uapi::drm_asahi_status_DRM_ASAHI_STATUS_KILLED,
                     WorkError::NoDevice => uapi::drm_asahi_status_DRM_ASAHI_STATUS_NO_DEVICE,
+                    WorkError::OutOfMemory => uapi::drm_asahi_status_DRM_ASAHI_STATUS_OUT_OF_MEMORY,
                     _ => uapi::drm_asahi_status_DRM_ASAHI_STATUS_UNKNOWN_ERROR,
                 },
                 ..Default::default()
@@ -96,6 +176,7 @@ impl From<WorkError> for kernel::error::Error {
             WorkError::Unknown => ENODATA,
             WorkError::Killed => ECANCELED,
             WorkError::NoDevice => ENODEV,
+            WorkError::OutOfMemory => ENOMEM,
         }
     }
 }
@@ -148,6 +229,7 @@ where
     error: Option<WorkError>,
     wptr: u32,
     vm_slot: u32,
+    submit_time: Duration,
     callback: Option<C>,
 }
 
@@ -156,6 +238,10 @@ pub(crate) trait GenSubmittedWork: Send + Sync {
     fn value(&self) -> event::EventValue;
     fn wptr(&self) -> u32;
     fn set_wptr(&mut self, wptr: u32);
+    fn submit_time(&self) -> Duration;
+    fn set_submit_time(&mut self, time: Duration);
+    fn vm_slot(&self) -> u32;
+    fn error(&self) -> Option<WorkError>;
     fn mark_error(&mut self, error: WorkError);
     fn complete(&mut self);
 }
@@ -179,6 +265,22 @@ impl<O: OpaqueGpuObject, C: FnOnce(&mut O, Option<WorkError>) + Send + Sync> Gen
         self.wptr = wptr;
     }
 
+    fn submit_time(&self) -> Duration {
+        self.submit_time
+    }
+
+    fn set_submit_time(&mut self, time: Duration) {
+        self.submit_time = time;
+    }
+
+    fn vm_slot(&self) -> u32 {
+        self.vm_slot
+    }
+
+    fn error(&self) -> Option<WorkError> {
+        self.error
+    }
+
     fn complete(&mut self) {
         if let Some(cb) = self.callback.take() {
             cb(&mut self.object, self.error);
@@ -194,6 +296,62 @@ impl<O: OpaqueGpuObject, C: FnOnce(&mut O, Option<WorkError>) + Send + Sync> Gen
     }
 }
 
+/// A snapshot of the occupancy of a work queue.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct WorkQueueStats {
+    /// Number of ring entries not yet consumed by the GPU, as of the last validated done pointer.
+    pub(crate) ring_used: u32,
+    /// Total number of ring entries.
+    pub(crate) ring_size: u32,
+    /// Number of submitted commands that have not completed yet.
+    pub(crate) pending_commands: usize,
+    /// Number of jobs that exist for this queue (open, committed or submitted).
+    pub(crate) jobs_in_flight: usize,
+    /// Number of submissions that had to wait for room in a full pipe channel.
+    pub(crate) submit_stalls: u64,
+    /// Total time spent waiting for room in a full pipe channel.
+    pub(crate) submit_stall_time: Duration,
+}
+
+/// A one-line summary of the in-flight work of a queue, for hang debugging.
+struct PendingSummary {
+    pipe_type: PipeType,
+    priority: u32,
+    slot: Option<u32>,
+    stats: WorkQueueStats,
+    /// Value and age of the oldest pending command.
+    oldest: Option<(event::EventValue, Duration)>,
+    last_submitted: Option<event::EventValue>,
+    last_completed: Option<event::EventValue>,
+}
+
+impl fmt::Display for PendingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (oldest_value, oldest_age) = match self.oldest {
+            Some((value, age)) => (Some(value), age),
+            None => (None, Duration::ZERO),
+        };
+
+        write!(
+            f,
+            "WorkQueue({:?}): prio {} slot {:?}: ring {}/{}, {} commands in flight, {} jobs, oldest {:?} ({} ms old), submitted {:?}, completed {:?}, {} stalls ({} ms)",
+            self.pipe_type,
+            self.priority,
+            self.slot,
+            self.stats.ring_used,
+            self.stats.ring_size,
+            self.stats.pending_commands,
+            self.stats.jobs_in_flight,
+            oldest_value,
+            oldest_age.as_millis(),
+            self.last_submitted,
+            self.last_completed,
+            self.stats.submit_stalls,
+            self.stats.submit_stall_time.as_millis()
+        )
+    }
+}
+
 /// Inner data for managing a single work queue.
 #[versions(AGX)]
 struct WorkQueueInner {
@@ -204,6 +362,8 @@ struct WorkQueueInner {
     pipe_type: PipeType,
     size: u32,
     wptr: u32,
+    /// Last GPU done pointer value observed and validated by the driver.
+    last_doneptr: u32,
     pending: Vec<Box<dyn GenSubmittedWork>>,
     last_token: Option<event::Token>,
     pending_jobs: usize,
@@ -214,6 +374,10 @@ struct WorkQueueInner {
     commit_seq: u64,
     submit_seq: u64,
     event_seq: u64,
+    /// See `WorkQueueStats::submit_stalls`.
+    submit_stalls: u64,
+    /// See `WorkQueueStats::submit_stall_time`.
+    submit_stall_time: Duration,
 }
 
 /// An instance of a work queue.
@@ -223,6 +387,9 @@ pub(crate) struct WorkQueue {
     info_pointer: GpuWeakPointer<QueueInfo::ver>,
     #[pin]
     inner: Mutex<WorkQueueInner::ver>,
+    /// Notified with `inner` locked whenever the queue goes idle, see `wait_idle()`.
+    #[pin]
+    idle_cond: CondVar,
 }
 
 #[versions(AGX)]
@@ -234,6 +401,30 @@ impl WorkQueueInner::ver {
             .state
             .with(|raw, _inner| raw.gpu_doneptr.load(Ordering::Acquire))
     }
+
+    /// Return the GPU done pointer, validating it against the ring state.
+    ///
+    /// A value that fails `validate_doneptr()` means the firmware and driver are out of sync, so
+    /// the ring math cannot be trusted and this returns an error instead. The caller is
+    /// responsible for kicking off recovery once it has dropped the queue lock.
+    fn checked_doneptr(&mut self) -> Result<u32> {
+        let doneptr = self.doneptr();
+
+        if validate_doneptr(doneptr, self.last_doneptr, self.wptr, self.size).is_none() {
+            pr_err!(
+                "WorkQueue({:?}): Ring corruption: doneptr {} (last {}, wptr {}, size {})\n",
+                self.pipe_type,
+                doneptr,
+                self.last_doneptr,
+                self.wptr,
+                self.size
+            );
+            return Err(EIO);
+        }
+
+        self.last_doneptr = doneptr;
+        Ok(doneptr)
+    }
 }
 
 #[versions(AGX)]
@@ -308,6 +499,7 @@ impl Job::ver {
             callback: Some(callback),
             wptr: 0,
             vm_slot,
+            submit_time: Duration::ZERO,
         })?)?;
 
         Ok(())
@@ -396,13 +588,27 @@ impl Job::ver {
 
         inner.pending.try_reserve(command_count)?;
 
+        let doneptr = match inner.checked_doneptr() {
+            Ok(doneptr) => doneptr,
+            Err(e) => {
+                // Recovery fails all pending work, which needs the queue lock.
+                let pipe_type = inner.pipe_type;
+                let dev = inner.dev.clone();
+                core::mem::drop(inner);
+                dev.data().gpu.handle_ring_error(pipe_type);
+                return Err(e);
+            }
+        };
+
         inner.last_submitted = inner.event.as_ref().map(|e| e.1);
 
+        let now = time::ktime_get();
         for mut command in self.pending.drain(..) {
             command.set_wptr(wptr);
+            command.set_submit_time(now);
 
             let next_wptr = (wptr + 1) % inner.size;
-            assert!(inner.doneptr() != next_wptr);
+            assert!(doneptr != next_wptr);
             inner.info.ring[wptr as usize] = command.gpu_va().get();
             wptr = next_wptr;
 
@@ -426,35 +632,122 @@ impl Job::ver {
 
 #[versions(AGX)]
 impl<'a> JobSubmission::ver<'a> {
-    pub(crate) fn run(mut self, channel: &mut channel::PipeChannel::ver) {
+    /// Hand this submission to the firmware via the given pipe channel.
+    ///
+    /// If the channel is full, this retries up to `max_retries` times with a short backoff
+    /// before giving up, or forever if `max_retries` is 0. On failure, the submission is dropped
+    /// and rolled back. On success, returns the number of retries that were needed.
+    pub(crate) fn run(
+        mut self,
+        channel: &mut channel::PipeChannel::ver,
+        max_retries: u32,
+    ) -> Result<u32> {
         let command_count = self.command_count;
-        let mut inner = self.inner.take().expect("No inner?");
         let wptr = self.wptr;
-        core::mem::forget(self);
+        let inner = self.inner.as_mut().expect("No inner?");
 
         inner
             .info
             .state
             .with(|raw, _inner| raw.cpu_wptr.store(wptr, Ordering::Release));
 
-        inner.wptr = wptr;
-
-        let event = inner.event.as_mut().expect("JobSubmission lost its event");
-
-        let event_slot = event.0.slot();
+        let event = inner.event.as_ref().expect("JobSubmission lost its event");
 
         let msg = fw::channels::RunWorkQueueMsg::ver {
             pipe_type: inner.pipe_type,
             work_queue: Some(inner.info.weak_pointer()),
-            wptr: inner.wptr,
-            event_slot,
+            wptr,
+            event_slot: event.0.slot(),
             is_new: inner.new,
             __pad: Default::default(),
         };
-        channel.send(&msg);
-        inner.new = false;
 
+        let mut retries = 0;
+        let mut stall_start = None;
+        loop {
+            match channel.try_send(&msg) {
+                Ok(()) => break,
+                Err(EAGAIN) if max_retries == 0 || retries < max_retries => {
+                    if retries == 0 {
+                        mod_pr_debug!(
+                            "WorkQueue({:?}): Pipe channel full, waiting\n",
+                            inner.pipe_type
+                        );
+                        inner.submit_stalls += 1;
+                        stall_start = Some(time::ktime_get());
+                    }
+                    retries = retries.saturating_add(1);
+                    coarse_sleep(Duration::from_millis(SUBMIT_RETRY_DELAY_MS));
+                }
+                Err(e) => {
+                    if let Some(start) = stall_start {
+                        inner.submit_stall_time += time::ktime_get().saturating_sub(start);
+                    }
+                    pr_err!(
+                        "WorkQueue({:?}): Pipe channel full, giving up after {} retries\n",
+                        inner.pipe_type,
+                        retries
+                    );
+                    let old_wptr = inner.wptr;
+                    inner
+                        .info
+                        .state
+                        .with(|raw, _inner| raw.cpu_wptr.store(old_wptr, Ordering::Release));
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut inner = self.inner.take().expect("No inner?");
+        core::mem::forget(self);
+
+        if let Some(start) = stall_start {
+            inner.submit_stall_time += time::ktime_get().saturating_sub(start);
+        }
+
+        inner.wptr = wptr;
+        inner.new = false;
         inner.submit_seq += command_count as u64;
+
+        Ok(retries)
+    }
+
+    /// Check that every GPU pointer this submission hands to the firmware is mapped in the
+    /// given (kernel) Vm, logging the offending field if not.
+    ///
+    /// This catches stale or wrong-Vm pointers before the firmware faults on them. Only the
+    /// pointers that the work queue itself embeds are checked; pointers nested inside the
+    /// command structures are not.
+    pub(crate) fn validate(&self, vm: &crate::mmu::Vm) -> Result {
+        let inner = self.inner.as_ref().expect("No inner?");
+        let mut ret = Ok(());
+
+        let info_va = inner.info.gpu_va().get();
+        if !vm.is_mapped(info_va as usize) {
+            pr_err!(
+                "WorkQueue({:?}): work_queue pointer {:#x} is not mapped\n",
+                inner.pipe_type,
+                info_va
+            );
+            ret = Err(EINVAL);
+        }
+
+        let mut wptr = inner.wptr;
+        while wptr != self.wptr {
+            let cmd_va = inner.info.ring[wptr as usize];
+            if !vm.is_mapped(cmd_va as usize) {
+                pr_err!(
+                    "WorkQueue({:?}): ring[{}] command pointer {:#x} is not mapped\n",
+                    inner.pipe_type,
+                    wptr,
+                    cmd_va
+                );
+                ret = Err(EINVAL);
+            }
+            wptr = (wptr + 1) % inner.size;
+        }
+
+        ret
     }
 
     pub(crate) fn pipe_type(&self) -> PipeType {
@@ -495,6 +788,7 @@ impl Drop for Job::ver {
             inner.event = None;
             inner.last_submitted = None;
             inner.last_completed = None;
+            self.wq.idle_cond.notify_all();
         }
         mod_pr_debug!("WorkQueue({:?}): Dropped Job\n", inner.pipe_type);
     }
@@ -533,6 +827,41 @@ impl WorkQueueInner::ver {
         self.size as usize - self.pending.len() - 1
     }
 
+    /// Return a snapshot of the queue occupancy.
+    ///
+    /// This does not read the done pointer back from the firmware, since it is also used while
+    /// the GPU is hung and its value cannot be trusted.
+    fn stats(&self) -> WorkQueueStats {
+        WorkQueueStats {
+            ring_used: ring_distance(self.last_doneptr, self.wptr, self.size),
+            ring_size: self.size,
+            pending_commands: self.pending.len(),
+            jobs_in_flight: self.pending_jobs,
+            submit_stalls: self.submit_stalls,
+            submit_stall_time: self.submit_stall_time,
+        }
+    }
+
+    /// Summarize the in-flight work, or return `None` if there is none.
+    fn pending_summary(&self, now: Duration) -> Option<PendingSummary> {
+        if self.pending.is_empty() && self.pending_jobs == 0 {
+            return None;
+        }
+
+        Some(PendingSummary {
+            pipe_type: self.pipe_type,
+            priority: self.priority,
+            slot: self.event.as_ref().map(|e| e.0.slot()),
+            stats: self.stats(),
+            oldest: self
+                .pending
+                .first()
+                .map(|cmd| (cmd.value(), now.saturating_sub(cmd.submit_time()))),
+            last_submitted: self.last_submitted,
+            last_completed: self.last_completed,
+        })
+    }
+
     pub(crate) fn free_slots(&self) -> usize {
         let busy_slots = if let Some(ls) = self.last_submitted {
             let lc = self
@@ -549,7 +878,9 @@ impl WorkQueueInner::ver {
 
 #[versions(AGX)]
 impl WorkQueue::ver {
-    /// Create a new WorkQueue of a given type and priority.
+    /// Create a new WorkQueue of a given type and priority, with a ring of `size` entries.
+    ///
+    /// Returns Err(EINVAL) if the ring size is out of range.
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         dev: &driver::AsahiDevice,
@@ -562,6 +893,11 @@ impl WorkQueue::ver {
         priority: u32,
         size: u32,
     ) -> Result<Arc<WorkQueue::ver>> {
+        if !(MIN_RING_SIZE..=MAX_RING_SIZE).contains(&size) {
+            pr_err!("WorkQueue({:?}): Invalid ring size {}\n", pipe_type, size);
+            return Err(EINVAL);
+        }
+
         let gpu_buf = alloc.private.array_empty(0x2c18)?;
         let shared = &mut alloc.shared;
         let inner = WorkQueueInner::ver {
@@ -618,6 +954,7 @@ impl WorkQueue::ver {
             pipe_type,
             size,
             wptr: 0,
+            last_doneptr: 0,
             pending: Vec::new(),
             last_token: None,
             event: None,
@@ -626,6 +963,8 @@ impl WorkQueue::ver {
             commit_seq: 0,
             submit_seq: 0,
             event_seq: 0,
+            submit_stalls: 0,
+            submit_stall_time: Duration::ZERO,
             last_completed: None,
             last_submitted: None,
         };
@@ -641,6 +980,7 @@ impl WorkQueue::ver {
         Arc::pin_init(pin_init!(Self {
             info_pointer,
             inner <- mutex_init,
+            idle_cond <- CondVar::new(c_str!("WorkQueue::idle_cond"), static_lock_class!()),
         }))
     }
 
@@ -711,17 +1051,66 @@ impl WorkQueue::ver {
         self.inner.lock().free_slots()
     }
 
+    /// Return a snapshot of the queue occupancy.
+    pub(crate) fn stats(&self) -> WorkQueueStats {
+        self.inner.lock().stats()
+    }
+
     pub(crate) fn pipe_type(&self) -> PipeType {
         self.inner.lock().pipe_type
     }
+
+    /// Wait for this queue to go idle, for teardown and suspend.
+    ///
+    /// Sleeps until the completion path or the last job going away reports the queue idle, for
+    /// at most `timeout_ms`. Returns `ETIMEDOUT` if it does not go idle in time, and `ERESTARTSYS`
+    /// if interrupted by a signal. This does not stop new jobs from being created, so the caller
+    /// must prevent further submissions for the result to be meaningful.
+    pub(crate) fn wait_idle(&self, timeout_ms: u32) -> Result {
+        let mut inner = self.inner.lock();
+        let mut remaining = time::msecs_to_jiffies(timeout_ms);
+
+        loop {
+            if inner.pending.is_empty() && inner.pending_jobs == 0 {
+                return Ok(());
+            }
+            if remaining == 0 {
+                pr_warn!(
+                    "WorkQueue({:?}): Timed out waiting for idle\n",
+                    inner.pipe_type
+                );
+                return Err(ETIMEDOUT);
+            }
+
+            remaining = match self.idle_cond.wait_timeout(&mut inner, remaining) {
+                CondVarTimeoutResult::Timeout => 0,
+                CondVarTimeoutResult::Woken { jiffies } => jiffies,
+                CondVarTimeoutResult::Signal { .. } => return Err(ERESTARTSYS),
+            };
+        }
+    }
+}
+
+/// Outcome of signaling a work queue.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct SignalResult {
+    /// Number of commands retired by this signal.
+    pub(crate) completed_commands: usize,
+    /// Whether the queue has no pending commands left.
+    pub(crate) now_empty: bool,
 }
 
 /// Trait used to erase the version-specific type of WorkQueues, to avoid leaking
 /// version-specificity into the event module.
 pub(crate) trait WorkQueue {
-    fn signal(&self) -> bool;
+    fn signal(&self) -> SignalResult;
     fn mark_error(&self, value: event::EventValue, error: WorkError);
     fn fail_all(&self, error: WorkError);
+    fn dump_pending(&self, now: Duration) -> bool;
+    fn show_pending(&self, m: &SeqFile, now: Duration);
+    fn wait_idle(&self, timeout_ms: u32) -> Result;
+    fn pipe_type(&self) -> PipeType;
+    fn stats(&self) -> WorkQueueStats;
 }
 
 #[versions(AGX)]
@@ -729,13 +1118,16 @@ impl WorkQueue for WorkQueue::ver {
     /// Signal a workqueue that some work was completed.
     ///
     /// This will check the event stamp value to find out exactly how many commands were processed.
-    fn signal(&self) -> bool {
+    fn signal(&self) -> SignalResult {
         let mut inner = self.inner.lock();
         let event = inner.event.as_ref();
         let value = match event {
             None => {
                 pr_err!("WorkQueue: signal() called but no event?\n");
-                return true;
+                return SignalResult {
+                    completed_commands: 0,
+                    now_empty: true,
+                };
             }
             Some(event) => event.0.current(),
         };
@@ -765,7 +1157,10 @@ impl WorkQueue for WorkQueue::ver {
         }
 
         if completed_commands == 0 {
-            return inner.pending.is_empty();
+            return SignalResult {
+                completed_commands: 0,
+                now_empty: inner.pending.is_empty(),
+            };
         }
 
         let mut completed = Vec::new();
@@ -807,11 +1202,24 @@ impl WorkQueue for WorkQueue::ver {
             inner.event = None;
             inner.last_submitted = None;
             inner.last_completed = None;
+            self.idle_cond.notify_all();
         }
 
         let dev = inner.dev.clone();
         core::mem::drop(inner);
 
+        if let Some(kind) = take_injected_error() {
+            if let Some(cmd) = completed.first_mut() {
+                pr_warn!(
+                    "WorkQueue({:?}): Injecting {:?} into command at value {:#x?}\n",
+                    pipe_type,
+                    kind,
+                    cmd.value()
+                );
+                cmd.mark_error(WorkError::injected(kind, cmd.vm_slot()));
+            }
+        }
+
         for cmd in completed.iter_mut() {
             cmd.complete();
         }
@@ -819,10 +1227,18 @@ impl WorkQueue for WorkQueue::ver {
         let gpu = &dev.data().gpu;
         gpu.add_completed_work(completed);
 
-        empty
+        SignalResult {
+            completed_commands,
+            now_empty: empty,
+        }
     }
 
     /// Mark this queue's work up to a certain stamp value as having failed.
+    ///
+    /// Several commands can share one stamp value, so the stamp alone does not say which of them
+    /// actually failed. Commands the GPU had already retired from the ring according to its done
+    /// pointer ran to completion and are left successful, so the error lands on the command that
+    /// was executing and everything after it.
     fn mark_error(&self, value: event::EventValue, error: WorkError) {
         // If anything is marked completed, we can consider it successful
         // at this point, even if we didn't get the signal event yet.
@@ -842,12 +1258,32 @@ impl WorkQueue for WorkQueue::ver {
             value
         );
 
-        for cmd in inner.pending.iter_mut() {
-            if cmd.value() <= value {
-                cmd.mark_error(error);
-            } else {
+        // Pending commands are in ring order, so the ones the done pointer has moved past are a
+        // prefix. If the done pointer cannot be trusted, fail everything in the stamp range.
+        let retired = match (inner.checked_doneptr(), inner.pending.first()) {
+            (Ok(doneptr), Some(first)) => ring_distance(first.wptr(), doneptr, inner.size) as usize,
+            _ => 0,
+        };
+
+        let pipe_type = inner.pipe_type;
+        let mut first_failed = true;
+        for (i, cmd) in inner.pending.iter_mut().enumerate() {
+            if cmd.value() > value {
                 break;
             }
+            if i < retired {
+                continue;
+            }
+            if first_failed {
+                mod_pr_debug!(
+                    "WorkQueue({:?}): {} commands completed before the failing one at wptr {}\n",
+                    pipe_type,
+                    i,
+                    cmd.wptr()
+                );
+                first_failed = false;
+            }
+            cmd.mark_error(error);
         }
     }
 
@@ -876,6 +1312,7 @@ impl WorkQueue for WorkQueue::ver {
 
         if inner.pending_jobs == 0 {
             inner.event = None;
+            self.idle_cond.notify_all();
         }
 
         core::mem::drop(inner);
@@ -885,4 +1322,106 @@ impl WorkQueue for WorkQueue::ver {
             cmd.complete();
         }
     }
+
+    /// Log a one-line summary of the in-flight work in this queue, if there is any.
+    ///
+    /// Returns whether there was any. The per-command detail is only available through
+    /// `show_pending()`, so a timeout does not flood the kernel log.
+    fn dump_pending(&self, now: Duration) -> bool {
+        let summary = self.inner.lock().pending_summary(now);
+
+        match summary {
+            Some(summary) => {
+                mod_pr_debug!("{}\n", summary);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write a summary of the in-flight work in this queue and one line per pending command.
+    fn show_pending(&self, m: &SeqFile, now: Duration) {
+        let inner = self.inner.lock();
+
+        let summary = match inner.pending_summary(now) {
+            Some(summary) => summary,
+            None => return,
+        };
+        seq_print!(m, "{}\n", summary);
+
+        // One line per command, in a stable format meant to be grepped.
+        for cmd in inner.pending.iter() {
+            seq_print!(
+                m,
+                "queue={:?} cmd value={:#x} wptr={} vm={} age_ms={} err={:?}\n",
+                inner.pipe_type,
+                cmd.value().raw(),
+                cmd.wptr(),
+                cmd.vm_slot(),
+                now.saturating_sub(cmd.submit_time()).as_millis(),
+                cmd.error()
+            );
+        }
+    }
+
+    /// Wait for this queue to go idle, see the inherent `WorkQueue::ver::wait_idle()`.
+    fn wait_idle(&self, timeout_ms: u32) -> Result {
+        // Inherent methods take precedence, so this does not recurse.
+        Self::wait_idle(self, timeout_ms)
+    }
+
+    fn pipe_type(&self) -> PipeType {
+        Self::pipe_type(self)
+    }
+
+    /// Return a snapshot of the queue occupancy, see the inherent `WorkQueue::ver::stats()`.
+    fn stats(&self) -> WorkQueueStats {
+        Self::stats(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ring_distance, validate_doneptr, MAX_RING_SIZE};
+
+    #[test]
+    fn test_ring_distance() {
+        assert_eq!(ring_distance(2, 2, 8), 0);
+        assert_eq!(ring_distance(2, 5, 8), 3);
+        assert_eq!(ring_distance(6, 1, 8), 3);
+        assert_eq!(ring_distance(1, 0, 8), 7);
+    }
+
+    #[test]
+    fn test_doneptr_valid() {
+        // No progress, partial progress, and catching up with the write pointer.
+        assert_eq!(validate_doneptr(2, 2, 5, 8), Some(2));
+        assert_eq!(validate_doneptr(4, 2, 5, 8), Some(4));
+        assert_eq!(validate_doneptr(5, 2, 5, 8), Some(5));
+        // Same, across the end of the ring.
+        assert_eq!(validate_doneptr(7, 6, 1, 8), Some(7));
+        assert_eq!(validate_doneptr(0, 6, 1, 8), Some(0));
+        assert_eq!(validate_doneptr(1, 6, 1, 8), Some(1));
+    }
+
+    #[test]
+    fn test_doneptr_out_of_range() {
+        assert_eq!(validate_doneptr(8, 2, 5, 8), None);
+        assert_eq!(validate_doneptr(u32::MAX, 2, 5, 8), None);
+        assert_eq!(validate_doneptr(u32::MAX, 0, 0, MAX_RING_SIZE), None);
+    }
+
+    #[test]
+    fn test_doneptr_backwards() {
+        assert_eq!(validate_doneptr(1, 2, 5, 8), None);
+        assert_eq!(validate_doneptr(5, 6, 1, 8), None);
+    }
+
+    #[test]
+    fn test_doneptr_past_wptr() {
+        assert_eq!(validate_doneptr(6, 2, 5, 8), None);
+        assert_eq!(validate_doneptr(2, 6, 1, 8), None);
+        // An idle ring cannot make progress at all.
+        assert_eq!(validate_doneptr(3, 2, 2, 8), None);
+    }
 }
@@ -0,0 +1,874 @@
+/* SPDX-License-Identifier: MIT */
+/*
+ * Copyright (C) The Asahi Linux Contributors
+ *
+ * Based on asahi_drm.h which is
+ *
+ * Copyright © 2014-2018 Broadcom
+ * Copyright © 2019 Collabora ltd.
+ */
+#ifndef _ASAHI_DRM_H_
+#define _ASAHI_DRM_H_
+
+#include "drm.h"
+
+#if defined(__cplusplus)
+extern "C" {
+#endif
+
+/*
+ * The UAPI defined in this file MUST NOT BE USED. End users, DO NOT attempt to
+ * use upstream Mesa with asahi kernels, it will blow up. Distro packagers, DO
+ * NOT patch upstream Mesa to do this.
+ */
+
+#define DRM_ASAHI_UNSTABLE_UABI_VERSION		10011
+
+#define DRM_ASAHI_GET_PARAMS			0x00
+#define DRM_ASAHI_VM_CREATE			0x01
+#define DRM_ASAHI_VM_DESTROY			0x02
+#define DRM_ASAHI_GEM_CREATE			0x03
+#define DRM_ASAHI_GEM_MMAP_OFFSET		0x04
+#define DRM_ASAHI_GEM_BIND			0x05
+#define DRM_ASAHI_QUEUE_CREATE			0x06
+#define DRM_ASAHI_QUEUE_DESTROY			0x07
+#define DRM_ASAHI_SUBMIT			0x08
+#define DRM_ASAHI_GEM_POPULATE			0x09
+#define DRM_ASAHI_PERFMON			0x0a
+#define DRM_ASAHI_GEM_BUSY			0x0b
+#define DRM_ASAHI_TRIM				0x0c
+#define DRM_ASAHI_GET_NAME			0x0d
+#define DRM_ASAHI_VM_INFO			0x0e
+#define DRM_ASAHI_GEM_BIND_BATCH		0x0f
+#define DRM_ASAHI_GEM_GET_IOVA			0x10
+#define DRM_ASAHI_GEM_SET_LABEL			0x11
+#define DRM_ASAHI_GEM_CREATE_BIND		0x12
+
+#define DRM_ASAHI_MAX_CLUSTERS	32
+#define DRM_ASAHI_MAX_FW_VERSION_LEN	4
+
+struct drm_asahi_params_global {
+	__u32 unstable_uabi_version;
+	__u32 pad0;
+
+	__u64 feat_compat;
+	__u64 feat_incompat;
+
+	__u32 gpu_generation;
+	__u32 gpu_variant;
+	__u32 gpu_revision;
+	__u32 chip_id;
+
+	__u32 num_dies;
+	__u32 num_clusters_total;
+	__u32 num_cores_per_cluster;
+	__u32 num_frags_per_cluster;
+	__u32 num_gps_per_cluster;
+	__u32 num_cores_total_active;
+	__u64 core_masks[DRM_ASAHI_MAX_CLUSTERS];
+
+	__u32 vm_page_size;
+	__u32 pad1;
+	__u64 vm_user_start;
+	__u64 vm_user_end;
+	__u64 vm_shader_start;
+	__u64 vm_shader_end;
+
+	__u32 max_syncs_per_submission;
+	__u32 max_commands_per_submission;
+	__u32 max_commands_in_flight;
+	__u32 max_attachments;
+
+	__u32 timer_frequency_hz;
+	__u32 min_frequency_khz;
+	__u32 max_frequency_khz;
+	__u32 max_power_mw;
+
+	__u32 result_render_size;
+	__u32 result_compute_size;
+
+	/*
+	 * GPU firmware version components, zero padded. Versions with more
+	 * components than fit are truncated.
+	 */
+	__u32 firmware_version[DRM_ASAHI_MAX_FW_VERSION_LEN];
+
+	__u32 num_queue_priorities;
+	__u32 num_mem_regions;
+
+	__u32 max_vms_per_file;
+	__u32 max_queues_per_file;
+};
+
+/*
+enum drm_asahi_feat_compat {
+};
+*/
+
+enum drm_asahi_feat_incompat {
+	DRM_ASAHI_FEAT_MANDATORY_ZS_COMPRESSION = (1UL) << 0,
+};
+
+struct drm_asahi_get_params {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @param_group: Parameter group to fetch (MBZ) */
+	__u32 param_group;
+
+	/** @pad: MBZ */
+	__u32 pad;
+
+	/** @pointer: User pointer to write parameter struct */
+	__u64 pointer;
+
+	/** @size: Size of user buffer, max size supported on return */
+	__u64 size;
+};
+
+/*
+ * The VM is only used for compute. It has no shader region, and binds into
+ * the shader region fail with EINVAL.
+ */
+#define ASAHI_VM_COMPUTE_ONLY	(1 << 0)
+
+struct drm_asahi_vm_create {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @vm_id: Returned VM ID */
+	__u32 vm_id;
+
+	/** @flags: Zero or more of ASAHI_VM_* */
+	__u32 flags;
+
+	/**
+	 * @user_start: Start of the general user mapping region, or 0 to use
+	 * the default region.
+	 *
+	 * Both bounds must be zero, or describe a page-aligned range above the
+	 * null page that does not overlap the shader region or the
+	 * kernel-managed regions. Set to the region in use on return.
+	 */
+	__u64 user_start;
+
+	/** @user_end: Inclusive end of the general user mapping region, or 0 */
+	__u64 user_end;
+};
+
+struct drm_asahi_vm_destroy {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @vm_id: VM ID to be destroyed */
+	__u32 vm_id;
+
+	/** @pad: MBZ */
+	__u32 pad;
+};
+
+#define ASAHI_GEM_WRITEBACK	(1 << 0)
+#define ASAHI_GEM_VM_PRIVATE	(1 << 1)
+
+struct drm_asahi_gem_create {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @size: Size of the BO */
+	__u64 size;
+
+	/** @flags: BO creation flags */
+	__u32 flags;
+
+	/** @vm_id: VM ID to assign to the BO, if ASAHI_GEM_VM_PRIVATE is set. */
+	__u32 vm_id;
+
+	/** @handle: Returned GEM handle for the BO */
+	__u32 handle;
+
+	/** @region: Memory region to allocate the BO from, below num_mem_regions */
+	__u32 region;
+};
+
+#define ASAHI_GEM_CACHING_WRITECOMBINE	0
+#define ASAHI_GEM_CACHING_WRITEBACK	1
+
+struct drm_asahi_gem_mmap_offset {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @handle: Handle for the object being mapped. */
+	__u32 handle;
+
+	/** @flags: Must be zero */
+	__u32 flags;
+
+	/** @offset: The fake offset to use for subsequent mmap call */
+	__u64 offset;
+
+	/** @caching: Returned CPU caching mode of the mapping, one of ASAHI_GEM_CACHING_* */
+	__u32 caching;
+
+	/** @pad: MBZ */
+	__u32 pad;
+};
+
+enum drm_asahi_bind_op {
+	ASAHI_BIND_OP_BIND = 0,
+	ASAHI_BIND_OP_UNBIND = 1,
+	ASAHI_BIND_OP_UNBIND_ALL = 2,
+};
+
+#define ASAHI_BIND_READ		(1 << 0)
+#define ASAHI_BIND_WRITE	(1 << 1)
+
+struct drm_asahi_gem_bind {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @op: Bind operation (enum drm_asahi_bind_op) */
+	__u32 op;
+
+	/** @flags: One or more of ASAHI_BIND_* */
+	__u32 flags;
+
+	/** @handle: GEM object to bind */
+	__u32 handle;
+
+	/** @vm_id: The ID of the VM to bind to */
+	__u32 vm_id;
+
+	/** @offset: Page-aligned offset into the object */
+	__u64 offset;
+
+	/** @range: Number of bytes from the object to bind to addr, within the object */
+	__u64 range;
+
+	/**
+	 * @addr: Address to bind to. For ASAHI_BIND_OP_UNBIND, the address of
+	 * the mapping to remove, with flags, offset, range and guard_pages
+	 * MBZ.
+	 */
+	__u64 addr;
+
+	/**
+	 * @guard_pages: Number of unmapped guard pages to reserve after the
+	 * mapping, at most 1024. Zero selects a single guard page.
+	 */
+	__u32 guard_pages;
+
+	/** @pad: MBZ */
+	__u32 pad;
+};
+
+enum drm_asahi_cmd_type {
+	DRM_ASAHI_CMD_RENDER = 0,
+	DRM_ASAHI_CMD_BLIT = 1,
+	DRM_ASAHI_CMD_COMPUTE = 2,
+};
+
+/* Note: this is an enum so that it can be resolved by Rust bindgen. */
+enum drm_asahi_queue_cap {
+	DRM_ASAHI_QUEUE_CAP_RENDER	= (1UL << DRM_ASAHI_CMD_RENDER),
+	DRM_ASAHI_QUEUE_CAP_BLIT	= (1UL << DRM_ASAHI_CMD_BLIT),
+	DRM_ASAHI_QUEUE_CAP_COMPUTE	= (1UL << DRM_ASAHI_CMD_COMPUTE),
+};
+
+struct drm_asahi_queue_create {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @vm_id: The ID of the VM this queue is bound to */
+	__u32 vm_id;
+
+	/** @queue_caps: Bitmask of DRM_ASAHI_QUEUE_CAP_* */
+	__u32 queue_caps;
+
+	/** @priority: Queue priority, below num_queue_priorities */
+	__u32 priority;
+
+	/** @queue_id: The returned queue ID */
+	__u32 queue_id;
+};
+
+struct drm_asahi_queue_destroy {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @queue_id: The queue ID to be destroyed */
+	__u32 queue_id;
+};
+
+enum drm_asahi_sync_type {
+	DRM_ASAHI_SYNC_SYNCOBJ = 0,
+	DRM_ASAHI_SYNC_TIMELINE_SYNCOBJ = 1,
+};
+
+struct drm_asahi_sync {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @sync_type: One of drm_asahi_sync_type */
+	__u32 sync_type;
+
+	/** @handle: The sync object handle */
+	__u32 handle;
+
+	/** @timeline_value: Timeline value for timeline sync objects */
+	__u64 timeline_value;
+};
+
+enum drm_asahi_subqueue {
+	DRM_ASAHI_SUBQUEUE_RENDER = 0, /* Also blit */
+	DRM_ASAHI_SUBQUEUE_COMPUTE = 1,
+	DRM_ASAHI_SUBQUEUE_COUNT = 2,
+};
+
+#define DRM_ASAHI_BARRIER_NONE ~(0U)
+
+struct drm_asahi_command {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @cmd_type: One of drm_asahi_cmd_type */
+	__u32 cmd_type;
+
+	/** @flags: Flags for command submission */
+	__u32 flags;
+
+	/** @cmd_buffer: Pointer to the appropriate command buffer structure */
+	__u64 cmd_buffer;
+
+	/** @cmd_buffer_size: Size of the command buffer structure */
+	__u64 cmd_buffer_size;
+
+	/** @result_offset: Offset into the result BO to return information about this command */
+	__u64 result_offset;
+
+	/** @result_size: Size of the result data structure */
+	__u64 result_size;
+
+	/** @barriers: Array of command indices per subqueue to wait on */
+	__u32 barriers[DRM_ASAHI_SUBQUEUE_COUNT];
+};
+
+/* Order prior CPU writes to the objects in sync_bos before the GPU is kicked */
+#define ASAHI_SUBMIT_SYNC_BOS	(1 << 0)
+
+struct drm_asahi_submit {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @in_syncs: An optional array of drm_asahi_sync to wait on before starting this job. */
+	__u64 in_syncs;
+
+	/** @out_syncs: An optional array of drm_asahi_sync objects to signal upon completion. */
+	__u64 out_syncs;
+
+	/** @commands: Pointer to the drm_asahi_command array of commands to submit. */
+	__u64 commands;
+
+	/** @flags: Zero or more of ASAHI_SUBMIT_* */
+	__u32 flags;
+
+	/** @queue_id: The queue ID to be submitted to */
+	__u32 queue_id;
+
+	/** @result_handle: An optional BO handle to place result data in */
+	__u32 result_handle;
+
+	/** @in_sync_count: Number of sync objects to wait on before starting this job. */
+	__u32 in_sync_count;
+
+	/** @out_sync_count: Number of sync objects to signal upon completion of this job. */
+	__u32 out_sync_count;
+
+	/** @command_count: Number of commands to be submitted */
+	__u32 command_count;
+
+	/**
+	 * @sync_bos: With ASAHI_SUBMIT_SYNC_BOS, pointer to an array of GEM
+	 * handles. Each object must be mapped into a VM of this file. MBZ
+	 * otherwise.
+	 */
+	__u64 sync_bos;
+
+	/** @sync_bo_count: Number of handles in sync_bos */
+	__u32 sync_bo_count;
+
+	/** @pad: MBZ */
+	__u32 pad;
+
+	/** @submission_id: Returned ID of this submission, as used in kernel logs */
+	__u64 submission_id;
+};
+
+/* FIXME: This doesn't make any sense, figure out exactly what the attachment flags are */
+#define ASAHI_ATTACHMENT_C    0
+#define ASAHI_ATTACHMENT_Z    1
+#define ASAHI_ATTACHMENT_S    2
+
+struct drm_asahi_attachment {
+	__u32 type;
+	__u32 size;
+	__u64 pointer;
+};
+
+#define ASAHI_RENDER_NO_CLEAR_PIPELINE_TEXTURES (1UL << 0)
+#define ASAHI_RENDER_SET_WHEN_RELOADING_Z_OR_S (1UL << 1)
+#define ASAHI_RENDER_VERTEX_SPILLS (1UL << 2)
+#define ASAHI_RENDER_PROCESS_EMPTY_TILES (1UL << 3)
+#define ASAHI_RENDER_NO_VERTEX_CLUSTERING (1UL << 4)
+#define ASAHI_RENDER_MSAA_ZS (1UL << 5)
+/* XXX check */
+#define ASAHI_RENDER_NO_PREEMPTION (1UL << 6)
+
+struct drm_asahi_cmd_render {
+	__u64 flags;
+
+	__u64 encoder_ptr;
+
+	__u64 attachments;
+	__u32 attachment_count;
+	__u32 pad;
+
+	__u64 depth_buffer_1;
+	__u64 depth_buffer_2;
+	__u64 depth_buffer_3;
+	__u64 depth_meta_buffer_1;
+	__u64 depth_meta_buffer_2;
+	__u64 depth_meta_buffer_3;
+
+	__u64 stencil_buffer_1;
+	__u64 stencil_buffer_2;
+	__u64 stencil_buffer_3;
+	__u64 stencil_meta_buffer_1;
+	__u64 stencil_meta_buffer_2;
+	__u64 stencil_meta_buffer_3;
+
+	__u64 scissor_array;
+	__u64 depth_bias_array;
+	__u64 visibility_result_buffer;
+
+	__u64 zls_ctrl;
+	__u64 ppp_multisamplectl;
+	__u32 ppp_ctrl;
+
+	__u32 fb_width;
+	__u32 fb_height;
+
+	__u32 utile_width;
+	__u32 utile_height;
+
+	__u32 samples;
+	__u32 layers;
+
+	__u32 encoder_id;
+	__u32 cmd_ta_id;
+	__u32 cmd_3d_id;
+
+	__u32 sample_size;
+	__u32 tib_blocks;
+	__u32 iogpu_unk_214;
+
+	__u32 merge_upper_x;
+	__u32 merge_upper_y;
+
+	__u32 load_pipeline;
+	__u32 load_pipeline_bind;
+
+	__u32 store_pipeline;
+	__u32 store_pipeline_bind;
+
+	__u32 partial_reload_pipeline;
+	__u32 partial_reload_pipeline_bind;
+
+	__u32 partial_store_pipeline;
+	__u32 partial_store_pipeline_bind;
+
+	__u32 depth_dimensions;
+	__u32 isp_bgobjdepth;
+	__u32 isp_bgobjvals;
+};
+
+struct drm_asahi_cmd_compute {
+	__u64 flags;
+
+	__u64 encoder_ptr;
+	__u64 encoder_end;
+
+	__u64 attachments;
+	__u32 attachment_count;
+	__u32 pad;
+
+	__u64 helper_program;
+	__u64 helper_arg;
+
+	__u32 encoder_id;
+	__u32 cmd_id;
+
+	__u32 iogpu_unk_40;
+	__u32 iogpu_unk_44;
+};
+
+enum drm_asahi_status {
+	DRM_ASAHI_STATUS_PENDING = 0,
+	DRM_ASAHI_STATUS_COMPLETE,
+	DRM_ASAHI_STATUS_UNKNOWN_ERROR,
+	DRM_ASAHI_STATUS_TIMEOUT,
+	DRM_ASAHI_STATUS_FAULT,
+	DRM_ASAHI_STATUS_KILLED,
+	DRM_ASAHI_STATUS_NO_DEVICE,
+	DRM_ASAHI_STATUS_OUT_OF_MEMORY,
+};
+
+enum drm_asahi_fault {
+	DRM_ASAHI_FAULT_NONE = 0,
+	DRM_ASAHI_FAULT_UNKNOWN,
+	DRM_ASAHI_FAULT_UNMAPPED,
+	DRM_ASAHI_FAULT_AF_FAULT,
+	DRM_ASAHI_FAULT_WRITE_ONLY,
+	DRM_ASAHI_FAULT_READ_ONLY,
+	DRM_ASAHI_FAULT_NO_ACCESS,
+};
+
+struct drm_asahi_result_info {
+	/** @status: One of enum drm_asahi_status */
+	__u32 status;
+
+	/** @fault_type: One of enum drm_asahi_fault */
+	__u32 fault_type;
+
+	/** @unit: Unit number, hardware dependent */
+	__u32 unit;
+
+	/** @sideband: Sideband information, hardware dependent */
+	__u32 sideband;
+
+	/** @level: Page table level at which the fault occurred, hardware dependent */
+	__u8 level;
+
+	/** @is_read: Fault was a read */
+	__u8 is_read;
+
+	/** @pad: MBZ */
+	__u16 pad;
+
+	/** @extra: Extra bits, hardware dependent */
+	__u32 extra;
+
+	/** @address: Fault address, cache line aligned */
+	__u64 address;
+};
+
+#define DRM_ASAHI_RESULT_RENDER_TVB_GROW_OVF (1UL << 0)
+#define DRM_ASAHI_RESULT_RENDER_TVB_GROW_MIN (1UL << 1)
+#define DRM_ASAHI_RESULT_RENDER_TVB_OVERFLOWED (1UL << 2)
+
+struct drm_asahi_result_render {
+	/** @address: Common result information */
+	struct drm_asahi_result_info info;
+
+	/** @flags: Zero or more of of DRM_ASAHI_RESULT_RENDER_* */
+	__u64 flags;
+
+	/** @vertex_ts_start: Timestamp of the start of vertex processing */
+	__u64 vertex_ts_start;
+
+	/** @vertex_ts_end: Timestamp of the end of vertex processing */
+	__u64 vertex_ts_end;
+
+	/** @fragment_ts_start: Timestamp of the start of fragment processing */
+	__u64 fragment_ts_start;
+
+	/** @fragment_ts_end: Timestamp of the end of fragment processing */
+	__u64 fragment_ts_end;
+
+	/** @tvb_size_bytes: TVB size at the start of this render */
+	__u64 tvb_size_bytes;
+
+	/** @tvb_usage_bytes: Total TVB usage in bytes for this render */
+	__u64 tvb_usage_bytes;
+
+	/** @num_tvb_overflows: Number of TVB overflows that occurred for this render */
+	__u32 num_tvb_overflows;
+};
+
+struct drm_asahi_result_compute {
+	/** @address: Common result information */
+	struct drm_asahi_result_info info;
+
+	/** @flags: Zero or more of of DRM_ASAHI_RESULT_COMPUTE_* */
+	__u64 flags;
+
+	/** @ts_start: Timestamp of the start of this compute command */
+	__u64 ts_start;
+
+	/** @ts_end: Timestamp of the end of this compute command */
+	__u64 ts_end;
+};
+
+/* Zero the pages of the object while populating it */
+#define ASAHI_POPULATE_ZERO	(1 << 0)
+
+struct drm_asahi_gem_populate {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @handle: GEM object to allocate all backing pages for */
+	__u32 handle;
+
+	/** @flags: Zero or more of ASAHI_POPULATE_* */
+	__u32 flags;
+};
+
+enum drm_asahi_perfmon_op {
+	ASAHI_PERFMON_OP_START = 0,
+	ASAHI_PERFMON_OP_STOP = 1,
+	ASAHI_PERFMON_OP_READ = 2,
+};
+
+struct drm_asahi_perfmon {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @op: Session operation (enum drm_asahi_perfmon_op) */
+	__u32 op;
+
+	/** @pad: MBZ */
+	__u32 pad;
+
+	/**
+	 * @pointer: For ASAHI_PERFMON_OP_READ, user pointer to write the
+	 * counter snapshot taken at start, followed by a current one
+	 */
+	__u64 pointer;
+
+	/** @size: Size of user buffer, size required on return */
+	__u64 size;
+};
+
+#define ASAHI_BUSY_READ		(1 << 0)
+#define ASAHI_BUSY_WRITE	(1 << 1)
+
+struct drm_asahi_gem_busy {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @handle: GEM object to query */
+	__u32 handle;
+
+	/**
+	 * @busy: Returned ASAHI_BUSY_* bits for the kinds of GPU access the
+	 * object may still be in use for
+	 */
+	__u32 busy;
+};
+
+struct drm_asahi_trim {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @pad: MBZ */
+	__u32 pad;
+};
+
+struct drm_asahi_get_name {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @pad: MBZ */
+	__u32 pad;
+
+	/** @pointer: User pointer to write the NUL-terminated GPU chip name */
+	__u64 pointer;
+
+	/**
+	 * @size: Size of user buffer. Set to the size of the name including
+	 * the NUL terminator on return, which is only written if it fits.
+	 */
+	__u64 size;
+};
+
+struct drm_asahi_vm_info {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @vm_id: The ID of the VM to query */
+	__u32 vm_id;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @user_start: Returned start of the VM's general user mapping region */
+	__u64 user_start;
+
+	/** @user_end: Returned inclusive end of the VM's general user mapping region */
+	__u64 user_end;
+
+	/**
+	 * @mapped_bytes: Returned number of bytes mapped in the VM, including
+	 * the driver's own mappings
+	 */
+	__u64 mapped_bytes;
+};
+
+struct drm_asahi_gem_bind_op {
+	/** @handle: GEM object to bind */
+	__u32 handle;
+
+	/** @flags: One or more of ASAHI_BIND_* */
+	__u32 flags;
+
+	/** @offset: Page-aligned offset into the object */
+	__u64 offset;
+
+	/** @range: Number of bytes from the object to bind to addr, within the object */
+	__u64 range;
+
+	/** @addr: Address to bind to */
+	__u64 addr;
+
+	/** @guard_pages: Number of guard pages, as for drm_asahi_gem_bind */
+	__u32 guard_pages;
+
+	/** @pad: MBZ */
+	__u32 pad;
+};
+
+struct drm_asahi_gem_bind_batch {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @vm_id: The ID of the VM to bind to */
+	__u32 vm_id;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @ops: Pointer to an array of drm_asahi_gem_bind_op */
+	__u64 ops;
+
+	/** @op_count: Number of operations in ops, at most 256 */
+	__u32 op_count;
+
+	/**
+	 * @failed_index: On failure, returned index of the operation that
+	 * failed, or ~0 if the failure is not specific to one operation. No
+	 * operation of a failed batch stays bound.
+	 */
+	__u32 failed_index;
+};
+
+struct drm_asahi_gem_get_iova {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @handle: GEM object to look up */
+	__u32 handle;
+
+	/** @vm_id: The ID of the VM to look up the mapping in */
+	__u32 vm_id;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @pad: MBZ */
+	__u32 pad;
+
+	/** @iova: Returned GPU address of the object in the VM */
+	__u64 iova;
+};
+
+struct drm_asahi_gem_set_label {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @handle: GEM object to label */
+	__u32 handle;
+
+	/** @flags: MBZ */
+	__u32 flags;
+
+	/** @label: User pointer to a UTF-8 label, not NUL terminated */
+	__u64 label;
+
+	/** @label_size: Size of the label in bytes, at most 64. Zero clears the label. */
+	__u32 label_size;
+
+	/** @pad: MBZ */
+	__u32 pad;
+};
+
+struct drm_asahi_gem_create_bind {
+	/** @extensions: Pointer to the first extension struct, if any */
+	__u64 extensions;
+
+	/** @size: Size of the BO */
+	__u64 size;
+
+	/** @flags: BO creation flags, as for drm_asahi_gem_create */
+	__u32 flags;
+
+	/** @region: Memory region to allocate the BO from, below num_mem_regions */
+	__u32 region;
+
+	/**
+	 * @vm_id: The ID of the VM to bind to, which the BO is private to if
+	 * ASAHI_GEM_VM_PRIVATE is set
+	 */
+	__u32 vm_id;
+
+	/** @bind_flags: One or more of ASAHI_BIND_* */
+	__u32 bind_flags;
+
+	/** @addr: Address to bind the whole BO to */
+	__u64 addr;
+
+	/** @guard_pages: Number of guard pages, as for drm_asahi_gem_bind */
+	__u32 guard_pages;
+
+	/** @handle: Returned GEM handle for the BO */
+	__u32 handle;
+
+	/** @iova: Returned GPU address of the BO */
+	__u64 iova;
+};
+
+/* Note: this is an enum so that it can be resolved by Rust bindgen. */
+enum {
+   DRM_IOCTL_ASAHI_GET_PARAMS       = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GET_PARAMS, struct drm_asahi_get_params),
+   DRM_IOCTL_ASAHI_VM_CREATE        = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_VM_CREATE, struct drm_asahi_vm_create),
+   DRM_IOCTL_ASAHI_VM_DESTROY       = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_VM_DESTROY, struct drm_asahi_vm_destroy),
+   DRM_IOCTL_ASAHI_GEM_CREATE       = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GEM_CREATE, struct drm_asahi_gem_create),
+   DRM_IOCTL_ASAHI_GEM_MMAP_OFFSET  = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GEM_MMAP_OFFSET, struct drm_asahi_gem_mmap_offset),
+   DRM_IOCTL_ASAHI_GEM_BIND         = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_GEM_BIND, struct drm_asahi_gem_bind),
+   DRM_IOCTL_ASAHI_QUEUE_CREATE     = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_QUEUE_CREATE, struct drm_asahi_queue_create),
+   DRM_IOCTL_ASAHI_QUEUE_DESTROY    = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_QUEUE_DESTROY, struct drm_asahi_queue_destroy),
+   DRM_IOCTL_ASAHI_SUBMIT           = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_SUBMIT, struct drm_asahi_submit),
+   DRM_IOCTL_ASAHI_GEM_POPULATE     = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_GEM_POPULATE, struct drm_asahi_gem_populate),
+   DRM_IOCTL_ASAHI_PERFMON          = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_PERFMON, struct drm_asahi_perfmon),
+   DRM_IOCTL_ASAHI_GEM_BUSY         = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GEM_BUSY, struct drm_asahi_gem_busy),
+   DRM_IOCTL_ASAHI_TRIM             = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_TRIM, struct drm_asahi_trim),
+   DRM_IOCTL_ASAHI_GET_NAME         = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GET_NAME, struct drm_asahi_get_name),
+   DRM_IOCTL_ASAHI_VM_INFO          = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_VM_INFO, struct drm_asahi_vm_info),
+   DRM_IOCTL_ASAHI_GEM_BIND_BATCH   = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GEM_BIND_BATCH, struct drm_asahi_gem_bind_batch),
+   DRM_IOCTL_ASAHI_GEM_GET_IOVA     = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GEM_GET_IOVA, struct drm_asahi_gem_get_iova),
+   DRM_IOCTL_ASAHI_GEM_SET_LABEL    = DRM_IOW(DRM_COMMAND_BASE + DRM_ASAHI_GEM_SET_LABEL, struct drm_asahi_gem_set_label),
+   DRM_IOCTL_ASAHI_GEM_CREATE_BIND  = DRM_IOWR(DRM_COMMAND_BASE + DRM_ASAHI_GEM_CREATE_BIND, struct drm_asahi_gem_create_bind),
+};
+
+#if defined(__cplusplus)
+}
+#endif
+
+#endif /* _ASAHI_DRM_H_ */
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: GPL-2.0 OR MIT
+
+//! DRM debugfs files.
+//!
+//! C header: [`include/drm/drm_debugfs.h`](../../../../include/drm/drm_debugfs.h)
+
+use crate::{bindings, drm, error::Result, seq_file::SeqFile, str::CStr, types::ForeignOwnable};
+use core::{ffi::c_int, ffi::c_void, marker::PhantomData};
+
+/// A read-only debugfs file of a DRM device, showing some of its driver data.
+pub trait ShowFile<T: drm::drv::Driver> {
+    /// The name of the file in the device's debugfs directory.
+    const NAME: &'static CStr;
+
+    /// Writes the contents of the file.
+    fn show(data: <T::Data as ForeignOwnable>::Borrowed<'_>, m: &SeqFile) -> Result;
+}
+
+struct ShowCallback<T: drm::drv::Driver, F: ShowFile<T>>(PhantomData<(T, F)>);
+
+impl<T: drm::drv::Driver, F: ShowFile<T>> ShowCallback<T, F> {
+    #[cfg_attr(not(CONFIG_DEBUG_FS), allow(dead_code))]
+    unsafe extern "C" fn show(m: *mut bindings::seq_file, _unused: *mut c_void) -> c_int {
+        // SAFETY: Files added with `drm_debugfs_add_file()` keep their `drm_debugfs_entry` in the
+        // seq file's private data, and the entry's device is valid while the file exists.
+        let dev = unsafe { (*((*m).private as *mut bindings::drm_debugfs_entry)).dev };
+        // SAFETY: The file is only created once the device is registered, which sets up its
+        // driver data, and removed before the device is unregistered, which frees it.
+        let data = unsafe { T::Data::borrow((*dev).dev_private) };
+        // SAFETY: `m` is valid for the duration of this callback.
+        let m = unsafe { SeqFile::from_raw(m) };
+
+        match F::show(data, m) {
+            Ok(()) => 0,
+            Err(e) => e.to_errno(),
+        }
+    }
+}
+
+impl<T: drm::drv::Driver> drm::device::Device<T> {
+    /// Adds a read-only debugfs file to the device.
+    ///
+    /// The file is created when the device is registered and removed along with it. This must be
+    /// called before the device is registered. Does nothing if debugfs is not enabled.
+    pub fn debugfs_add_file<F: ShowFile<T>>(&self) {
+        // SAFETY: `self.drm` is a valid DRM device, and both the name and the callback are
+        // static.
+        #[cfg(CONFIG_DEBUG_FS)]
+        unsafe {
+            bindings::drm_debugfs_add_file(
+                self.drm.get(),
+                F::NAME.as_char_ptr(),
+                Some(ShowCallback::<T, F>::show),
+                core::ptr::null_mut(),
+            )
+        };
+    }
+}
@@ -57,6 +57,7 @@ pub mod of;
 pub mod platform;
 pub mod prelude;
 pub mod print;
+pub mod seq_file;
 pub mod siphash;
 pub mod soc;
 mod static_assert;
@@ -68,6 +69,7 @@ pub mod task;
 pub mod time;
 pub mod types;
 pub mod user_ptr;
+pub mod workqueue;
 pub mod xarray;
 
 #[doc(hidden)]
@@ -39,6 +39,8 @@ impl<T: Driver> driver::DriverOps for Adapter<T> {
         pdrv.driver.name = name.as_char_ptr();
         pdrv.probe = Some(Self::probe_callback);
         pdrv.remove = Some(Self::remove_callback);
+        pdrv.suspend = Some(Self::suspend_callback);
+        pdrv.resume = Some(Self::resume_callback);
         if let Some(t) = T::OF_DEVICE_ID_TABLE {
             pdrv.driver.of_match_table = t.as_ref();
         }
@@ -46,7 +48,7 @@ impl<T: Driver> driver::DriverOps for Adapter<T> {
         //   - `pdrv` lives at least until the call to `platform_driver_unregister()` returns.
         //   - `name` pointer has static lifetime.
         //   - `module.0` lives at least as long as the module.
-        //   - `probe()` and `remove()` are static functions.
+        //   - `probe()`, `remove()`, `suspend()` and `resume()` are static functions.
         //   - `of_match_table` is either a raw pointer with static lifetime,
         //      as guaranteed by the [`driver::IdTable`] type, or null.
         to_result(unsafe { bindings::__platform_driver_register(reg, module.0) })
@@ -123,6 +125,33 @@ impl<T: Driver> Adapter<T> {
             Ok(0)
         })
     }
+
+    extern "C" fn suspend_callback(
+        pdev: *mut bindings::platform_device,
+        _state: bindings::pm_message_t,
+    ) -> core::ffi::c_int {
+        from_result(|| {
+            // SAFETY: `pdev` is guaranteed to be a valid, non-null pointer.
+            let ptr = unsafe { bindings::platform_get_drvdata(pdev) };
+            // SAFETY: The driver data was set by `probe` using `T::Data::into_foreign`, and is
+            // only converted back in `remove`, which cannot run concurrently with suspend.
+            let data = unsafe { T::Data::borrow(ptr) };
+            T::suspend(data)?;
+            Ok(0)
+        })
+    }
+
+    extern "C" fn resume_callback(pdev: *mut bindings::platform_device) -> core::ffi::c_int {
+        from_result(|| {
+            // SAFETY: `pdev` is guaranteed to be a valid, non-null pointer.
+            let ptr = unsafe { bindings::platform_get_drvdata(pdev) };
+            // SAFETY: The driver data was set by `probe` using `T::Data::into_foreign`, and is
+            // only converted back in `remove`, which cannot run concurrently with resume.
+            let data = unsafe { T::Data::borrow(ptr) };
+            T::resume(data)?;
+            Ok(0)
+        })
+    }
 }
 
 /// A platform driver.
@@ -155,6 +184,21 @@ pub trait Driver {
     fn remove(_data: &Self::Data) -> Result {
         Ok(())
     }
+
+    /// Platform driver suspend.
+    ///
+    /// Called on system suspend. Implementers should quiesce the device here. Returning an error
+    /// aborts the suspend.
+    fn suspend(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Ok(())
+    }
+
+    /// Platform driver resume.
+    ///
+    /// Called on system resume, to undo the effects of a successful [`Driver::suspend`].
+    fn resume(_data: <Self::Data as ForeignOwnable>::Borrowed<'_>) -> Result {
+        Ok(())
+    }
 }
 
 /// A platform device.
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Seq files.
+//!
+//! This is used to write the contents of virtual files, such as debugfs files.
+//!
+//! C header: [`include/linux/seq_file.h`](../../../../include/linux/seq_file.h)
+
+use crate::{bindings, c_str, types::Opaque};
+use core::fmt;
+
+/// A seq file that is being written to.
+///
+/// # Invariants
+///
+/// `inner` is a valid `struct seq_file` that is being shown.
+#[repr(transparent)]
+pub struct SeqFile {
+    inner: Opaque<bindings::seq_file>,
+}
+
+impl SeqFile {
+    /// Creates a new [`SeqFile`] from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point at a valid `struct seq_file` for the lifetime `'a`, and the returned
+    /// reference may only be used from the `show` callback it was passed to.
+    pub unsafe fn from_raw<'a>(ptr: *mut bindings::seq_file) -> &'a SeqFile {
+        // SAFETY: The caller ensures that the pointer is valid for `'a`, and `SeqFile` has the
+        // same layout as `struct seq_file`.
+        unsafe { &*ptr.cast() }
+    }
+
+    /// Appends formatted output to the file. Use [`seq_print`] instead.
+    pub fn call_printf(&self, args: fmt::Arguments<'_>) {
+        // SAFETY: `self.inner` is valid by the type invariants. The "%pA" format string expects a
+        // pointer to `fmt::Arguments`, which is what we're passing as the last argument.
+        unsafe {
+            bindings::seq_printf(
+                self.inner.get(),
+                c_str!("%pA").as_char_ptr(),
+                &args as *const _ as *const core::ffi::c_void,
+            );
+        }
+    }
+}
+
+/// Writes to a [`SeqFile`] using the same syntax as [`core::format_args!`].
+///
+/// Output that does not fit the buffer is retried by the seq file core with a larger one, so
+/// there is nothing to handle here.
+#[macro_export]
+macro_rules! seq_print {
+    ($m:expr, $($arg:tt)+) => (
+        $m.call_printf(format_args!($($arg)+))
+    );
+}
@@ -7,6 +7,7 @@
 
 mod arc;
 mod condvar;
+mod condvar_timeout;
 pub mod lock;
 mod locked_by;
 
@@ -19,6 +20,7 @@ use no_lockdep as lockdep;
 
 pub use arc::{Arc, ArcBorrow, UniqueArc};
 pub use condvar::CondVar;
+pub use condvar_timeout::CondVarTimeoutResult;
 pub use lock::{mutex::Mutex, spinlock::SpinLock};
 pub use lockdep::{LockClassKey, StaticLockClassKey};
 pub use locked_by::LockedBy;
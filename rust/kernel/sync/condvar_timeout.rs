@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Timed waits on condition variables.
+
+use super::{
+    lock::{Backend, Guard},
+    CondVar,
+};
+use crate::{bindings, time::Jiffies, types::Opaque};
+
+/// The return type of [`CondVar::wait_timeout`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CondVarTimeoutResult {
+    /// The timeout was reached.
+    Timeout,
+    /// Somebody woke us up.
+    Woken {
+        /// Remaining sleep duration.
+        jiffies: Jiffies,
+    },
+    /// A signal occurred.
+    Signal {
+        /// Remaining sleep duration.
+        jiffies: Jiffies,
+    },
+}
+
+impl CondVar {
+    /// Releases the lock and waits for a notification in interruptible mode, for at most
+    /// `jiffies`.
+    ///
+    /// Atomically releases the given lock (whose ownership is proven by the guard) and puts the
+    /// thread to sleep. It wakes up when notified by [`CondVar::notify_one`] or
+    /// [`CondVar::notify_all`], when the timeout expires, or when the thread receives a signal.
+    /// The lock is reacquired before returning.
+    ///
+    /// Like [`CondVar::wait`], this may also wake up spuriously, so callers must recheck their
+    /// condition and wait again with the remaining time if it does not hold yet.
+    pub fn wait_timeout<T: ?Sized, B: Backend>(
+        &self,
+        guard: &mut Guard<'_, T, B>,
+        jiffies: Jiffies,
+    ) -> CondVarTimeoutResult {
+        let wait = Opaque::<bindings::wait_queue_entry>::uninit();
+
+        // SAFETY: `wait` points to valid memory.
+        unsafe { bindings::init_wait(wait.get()) };
+
+        // SAFETY: Both `wait` and `wait_list` point to valid memory.
+        unsafe {
+            bindings::prepare_to_wait_exclusive(
+                self.wait_list.get(),
+                wait.get(),
+                bindings::TASK_INTERRUPTIBLE as _,
+            )
+        };
+
+        // `schedule_timeout()` takes a signed timeout, anything larger is as good as forever.
+        let timeout = jiffies.min(core::ffi::c_long::MAX as Jiffies) as core::ffi::c_long;
+        let mut remaining = 0;
+
+        // SAFETY: `schedule_timeout()` switches to another thread until woken up or timed out,
+        // and the task state was set by `prepare_to_wait_exclusive()` above.
+        guard.do_unlocked(|| remaining = unsafe { bindings::schedule_timeout(timeout) });
+
+        // SAFETY: Both `wait` and `wait_list` point to valid memory.
+        unsafe { bindings::finish_wait(self.wait_list.get(), wait.get()) };
+
+        // `schedule_timeout()` never returns a negative value for a non-negative timeout.
+        let remaining = remaining as Jiffies;
+
+        if crate::current!().signal_pending() {
+            CondVarTimeoutResult::Signal { jiffies: remaining }
+        } else if remaining == 0 {
+            CondVarTimeoutResult::Timeout
+        } else {
+            CondVarTimeoutResult::Woken { jiffies: remaining }
+        }
+    }
+}
@@ -8,6 +8,20 @@
 use crate::bindings;
 use core::time::Duration;
 
+/// The time unit of Linux kernel. One jiffy equals (1/HZ) second.
+pub type Jiffies = core::ffi::c_ulong;
+
+/// The millisecond time unit.
+pub type Msecs = core::ffi::c_uint;
+
+/// Converts milliseconds to jiffies.
+#[inline]
+pub fn msecs_to_jiffies(msecs: Msecs) -> Jiffies {
+    // SAFETY: The `__msecs_to_jiffies` function is always safe to call no matter what the argument
+    // is.
+    unsafe { bindings::__msecs_to_jiffies(msecs) }
+}
+
 /// Returns the kernel time elapsed since boot, excluding time spent sleeping, as a [`Duration`].
 pub fn ktime_get() -> Duration {
     Duration::from_nanos(unsafe { bindings::ktime_get() }.try_into().unwrap())
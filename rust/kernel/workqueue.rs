@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-2.0
+
+//! Work queues.
+//!
+//! This allows running deferred work on the system work queue, for work that should not be done
+//! in the context that notices it is needed.
+//!
+//! C header: [`include/linux/workqueue.h`](../../../../include/linux/workqueue.h)
+
+use crate::{
+    bindings,
+    init::PinInit,
+    macros::pin_data,
+    pin_init,
+    str::CStr,
+    sync::{Arc, LockClassKey},
+    types::{ForeignOwnable, Opaque},
+};
+use core::{
+    ffi::c_void,
+    marker::PhantomData,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// An item that can run on the system work queue, by embedding a [`Work`].
+pub trait WorkItem: Send + Sync + Sized + 'static {
+    /// Returns the [`Work`] embedded in this item.
+    fn work(&self) -> &Work<Self>;
+
+    /// Runs the item on the system work queue, once for every [`Work::enqueue`] that queued it.
+    fn run(this: Arc<Self>);
+}
+
+/// A work queue entry embedded in a [`WorkItem`].
+///
+/// While queued, the entry holds a reference to its item, so the item stays alive until it ran.
+///
+/// # Invariants
+///
+/// `work` is an initialised `struct work_struct` whose function is `Work::<T>::run_raw`. While it
+/// is pending, `owner` is a pointer returned by `Arc::<T>::into_foreign()` for the item this
+/// `Work` is embedded in.
+#[repr(C)]
+#[pin_data]
+pub struct Work<T: WorkItem> {
+    #[pin]
+    work: Opaque<bindings::work_struct>,
+    owner: AtomicPtr<c_void>,
+    _p: PhantomData<T>,
+}
+
+// SAFETY: A `Work` only ever hands its item to the work queue thread, which is fine since the item
+// is `Send + Sync`. The C side synchronises access to the `work_struct` itself.
+unsafe impl<T: WorkItem> Send for Work<T> {}
+// SAFETY: See above. `owner` is atomic.
+unsafe impl<T: WorkItem> Sync for Work<T> {}
+
+impl<T: WorkItem> Work<T> {
+    /// Creates a new work queue entry.
+    pub fn new(name: &'static CStr, key: LockClassKey) -> impl PinInit<Self> {
+        pin_init!(Self {
+            // SAFETY: `init_work_with_key()` initialises the `work_struct` in place, and the
+            // function we pass has the signature the work queue expects.
+            work <- Opaque::ffi_init(|slot| unsafe {
+                bindings::init_work_with_key(
+                    slot,
+                    Some(Self::run_raw),
+                    false,
+                    name.as_char_ptr(),
+                    key.as_ptr(),
+                )
+            }),
+            owner: AtomicPtr::new(ptr::null_mut()),
+            _p: PhantomData,
+        })
+    }
+
+    /// Queues an item on the system work queue.
+    ///
+    /// Returns `false` if the item was already pending, in which case it only runs once. Items
+    /// should therefore process everything that was handed to them when they run.
+    pub fn enqueue(this: Arc<T>) -> bool {
+        let work: *const Work<T> = this.work();
+        let owner = this.into_foreign();
+
+        // SAFETY: The `Work` is embedded in the item, which stays alive at least until the
+        // reference we just took is released, either below or by `run_raw()`.
+        let work = unsafe { &*work };
+
+        // If the work is already pending, this stores the same pointer again.
+        work.owner.store(owner as *mut c_void, Ordering::Relaxed);
+
+        // SAFETY: `work.work` is initialised by the type invariants, and `system_wq` is always
+        // valid. Queueing implies a full barrier, so `run_raw()` observes the store above.
+        let queued = unsafe {
+            bindings::queue_work_on(
+                bindings::WORK_CPU_UNBOUND as _,
+                bindings::system_wq,
+                work.work.get(),
+            )
+        };
+
+        if !queued {
+            // SAFETY: `owner` came from `into_foreign()` above, and the pending work already
+            // holds its own reference.
+            drop(unsafe { Arc::<T>::from_foreign(owner) });
+        }
+
+        queued
+    }
+
+    /// Waits until the work has finished running, if it is pending or running.
+    pub fn flush(&self) {
+        // SAFETY: `self.work` is initialised by the type invariants.
+        unsafe { bindings::flush_work(self.work.get()) };
+    }
+
+    unsafe extern "C" fn run_raw(work: *mut bindings::work_struct) {
+        // SAFETY: `work` is the first field of the `#[repr(C)]` `Work<T>`, and `Opaque` is
+        // `#[repr(transparent)]`, so this is the `Work<T>` the entry was queued from.
+        let work = unsafe { &*(work as *const Work<T>) };
+        let owner = work.owner.load(Ordering::Relaxed);
+
+        // SAFETY: By the type invariants, `owner` is the reference `enqueue()` took for this run.
+        T::run(unsafe { Arc::<T>::from_foreign(owner) });
+    }
+}
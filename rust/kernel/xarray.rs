@@ -6,7 +6,7 @@
 
 use crate::{
     bindings,
-    error::{Error, Result},
+    error::{code::*, Error, Result},
     types::{ForeignOwnable, Opaque, ScopeGuard},
 };
 use core::{
@@ -169,6 +169,20 @@ impl<T: ForeignOwnable> XArray<T> {
     ///
     /// This guard blocks all other actions on the `XArray`. Callers are expected to drop the
     /// `Guard` eagerly to avoid blocking other users, such as by taking a clone of the value.
+    ///
+    /// A lookup that misses releases the lock before returning `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::xarray::XArray;
+    /// let xa = Box::into_pin(Box::try_new(XArray::<Box<u32>>::new(0))?);
+    ///
+    /// assert!(xa.as_ref().get(1).is_none());
+    /// xa.as_ref().set(1, Box::try_new(42)?)?;
+    /// assert_eq!(*xa.as_ref().get(1).unwrap().borrow(), 42);
+    /// # Ok::<(), Error>(())
+    /// ```
     pub fn get(self: Pin<&Self>, index: usize) -> Option<Guard<'_, T>> {
         // SAFETY: `self.xa` is always valid by the type invariant.
         unsafe { bindings::xa_lock(self.xa.get()) };
@@ -185,6 +199,208 @@ impl<T: ForeignOwnable> XArray<T> {
         })
     }
 
+    /// Looks up an entry in the array, inserting a new value created by `f` if the slot is empty.
+    ///
+    /// Returns a `Guard` to the existing or newly inserted entry, with the same locking semantics
+    /// as `get()`. `f` is called without the `XArray` lock held, so it may sleep or allocate. The
+    /// new value is inserted atomically with respect to other users: if another value was stored
+    /// at `index` in the meantime, the value returned by `f` is dropped and the existing entry is
+    /// returned instead. If `f` fails, its error is returned and the slot is left empty.
+    ///
+    /// Returns `EBUSY` if the slot is held by a `Reservation`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::xarray::XArray;
+    /// let xa = Box::into_pin(Box::try_new(XArray::<Box<u32>>::new(0))?);
+    ///
+    /// assert_eq!(*xa.as_ref().get_or_insert_with(4, || Ok(Box::try_new(1)?))?.borrow(), 1);
+    /// assert_eq!(*xa.as_ref().get_or_insert_with(4, || Ok(Box::try_new(2)?))?.borrow(), 1);
+    ///
+    /// assert!(xa.as_ref().get_or_insert_with(5, || Err(ENOMEM)).is_err());
+    /// assert!(xa.as_ref().get(5).is_none());
+    /// # Ok::<(), Error>(())
+    /// ```
+    ///
+    /// Reserved slots are not filled:
+    ///
+    /// ```
+    /// # use kernel::xarray::{flags, XArray};
+    /// let xa = Box::into_pin(Box::try_new(XArray::<Box<u32>>::new(flags::ALLOC))?);
+    ///
+    /// let reservation = xa.as_ref().reserve()?;
+    /// let index = reservation.index();
+    /// assert_eq!(
+    ///     xa.as_ref().get_or_insert_with(index, || Ok(Box::try_new(1)?)).err(),
+    ///     Some(EBUSY)
+    /// );
+    /// assert!(xa.as_ref().get(index).is_none());
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn get_or_insert_with<F: FnOnce() -> Result<T>>(
+        self: Pin<&Self>,
+        index: usize,
+        f: F,
+    ) -> Result<Guard<'_, T>> {
+        let xa_index = index.try_into()?;
+
+        if let Some(guard) = self.get(index) {
+            return Ok(guard);
+        }
+
+        let new = f()?.into_foreign();
+        // SAFETY: `new` just came from into_foreign(), and we dismiss this guard if
+        // the __xa_insert operation succeeds and takes ownership of the pointer.
+        let value_guard = ScopeGuard::new(|| unsafe {
+            T::from_foreign(new);
+        });
+        let ptr = NonNull::new(new as *mut T).ok_or(EINVAL)?;
+
+        // SAFETY: `self.xa` is always valid by the type invariant.
+        unsafe { bindings::xa_lock(self.xa.get()) };
+
+        // SAFETY: `self.xa` is always valid by the type invariant. This is declared after
+        // `value_guard`, so on error paths the lock is released before the value is dropped.
+        let lock_guard = ScopeGuard::new(|| unsafe { bindings::xa_unlock(self.xa.get()) });
+
+        // SAFETY: `self.xa` is always valid by the type invariant, we hold the lock, and we are
+        // storing a `T::into_foreign()` result which upholds the later invariants. This may drop
+        // and reacquire the lock to allocate memory.
+        let ret = unsafe {
+            bindings::__xa_insert(self.xa.get(), xa_index, new as *mut _, bindings::GFP_KERNEL)
+        };
+
+        if ret == EBUSY.to_errno() {
+            // The slot is occupied (we lost the race against another store) or reserved. Drop
+            // our value outside the lock and return whatever is there now, if anything. Reserved
+            // slots read back as empty, so they fail with `EBUSY`.
+            drop(lock_guard);
+            drop(value_guard);
+            self.get(index).ok_or(EBUSY)
+        } else if ret != 0 {
+            Err(Error::from_errno(ret))
+        } else {
+            value_guard.dismiss();
+            lock_guard.dismiss();
+            // INVARIANT: We hold the lock, which is released when the `Guard` is dropped.
+            Ok(Guard(ptr, self))
+        }
+    }
+
+    /// Calls a function for every present entry in the array, in index order.
+    ///
+    /// The callback receives the index and a borrowed reference to each entry. The `XArray` lock
+    /// is held for the whole iteration, so the callback must not call back into this `XArray`
+    /// (which would deadlock) and must not sleep.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::xarray::{flags, XArray};
+    /// let xa = Box::into_pin(Box::try_new(XArray::<Box<u32>>::new(flags::ALLOC))?);
+    ///
+    /// for i in 1..=3u32 {
+    ///     xa.as_ref().alloc(Box::try_new(i)?)?;
+    /// }
+    ///
+    /// let mut sum = 0;
+    /// let mut count = 0;
+    /// xa.as_ref().for_each(|_index, value| {
+    ///     sum += *value;
+    ///     count += 1;
+    /// });
+    /// assert_eq!(count, 3);
+    /// assert_eq!(sum, 6);
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn for_each<F: FnMut(usize, T::Borrowed<'_>)>(self: Pin<&Self>, mut f: F) {
+        // SAFETY: `self.xa` is always valid by the type invariant.
+        unsafe { bindings::xa_lock(self.xa.get()) };
+
+        // SAFETY: `self.xa` is always valid by the type invariant.
+        let _guard = ScopeGuard::new(|| unsafe { bindings::xa_unlock(self.xa.get()) });
+
+        let mut index: core::ffi::c_ulong = 0;
+        // SAFETY: `self.xa` is always valid by the type invariant, and we hold the lock.
+        let mut entry = unsafe {
+            bindings::xa_find(
+                self.xa.get(),
+                &mut index,
+                core::ffi::c_ulong::MAX,
+                bindings::BINDINGS_XA_PRESENT,
+            )
+        };
+
+        while !entry.is_null() {
+            // SAFETY: Non-NULL entries are valid `ForeignOwnable` pointers stored by `replace()`
+            // or `alloc_limits_opt()`, and the lock we hold keeps them in the array for the
+            // duration of the borrow.
+            f(index as usize, unsafe { T::borrow(entry) });
+
+            // SAFETY: `self.xa` is always valid by the type invariant, and we hold the lock.
+            entry = unsafe {
+                bindings::xa_find_after(
+                    self.xa.get(),
+                    &mut index,
+                    core::ffi::c_ulong::MAX,
+                    bindings::BINDINGS_XA_PRESENT,
+                )
+            };
+        }
+    }
+
+    /// Returns `true` if the array has no present entries.
+    ///
+    /// Reserved slots that have not been filled yet do not count as present.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use kernel::xarray::{flags, XArray};
+    /// let xa = Box::into_pin(Box::try_new(XArray::<Box<u32>>::new(flags::ALLOC))?);
+    ///
+    /// assert!(xa.as_ref().is_empty());
+    /// assert_eq!(xa.as_ref().count(), 0);
+    ///
+    /// let index = xa.as_ref().alloc(Box::try_new(1)?)?;
+    /// assert!(!xa.as_ref().is_empty());
+    /// assert_eq!(xa.as_ref().count(), 1);
+    ///
+    /// for i in 0..16u32 {
+    ///     xa.as_ref().alloc(Box::try_new(i)?)?;
+    /// }
+    /// assert_eq!(xa.as_ref().count(), 17);
+    ///
+    /// xa.as_ref().remove(index);
+    /// assert_eq!(xa.as_ref().count(), 16);
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn is_empty(self: Pin<&Self>) -> bool {
+        let mut index: core::ffi::c_ulong = 0;
+        // SAFETY: `self.xa` is always valid by the type invariant. `xa_find` takes the RCU read
+        // lock internally and we never dereference the returned entry.
+        let entry = unsafe {
+            bindings::xa_find(
+                self.xa.get(),
+                &mut index,
+                core::ffi::c_ulong::MAX,
+                bindings::BINDINGS_XA_PRESENT,
+            )
+        };
+        entry.is_null()
+    }
+
+    /// Returns the number of present entries in the array.
+    ///
+    /// This walks the whole array with the `XArray` lock held, so it is intended for diagnostics
+    /// and teardown paths rather than hot paths. See `is_empty()` for a cheaper emptiness check.
+    pub fn count(self: Pin<&Self>) -> usize {
+        let mut count = 0;
+        self.for_each(|_, _| count += 1);
+        count
+    }
+
     /// Removes and returns an entry, returning it if it existed.
     pub fn remove(self: Pin<&Self>, index: usize) -> Option<T> {
         let p = unsafe { bindings::xa_erase(self.xa.get(), index.try_into().ok()?) };